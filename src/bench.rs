@@ -0,0 +1,165 @@
+//! Fixed depth searches of a fixed set of positions. The total node count is
+//! a signature of the search: it changes when, and only when, the search
+//! does, which makes it a quick check that a change has no functional effect.
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    board::Board,
+    search::{Limits, SearchControl, SearchResult, Searcher},
+};
+
+/// Openings, middlegames and endings, with a few checks, mates and stalemates.
+pub const POSITIONS: [&str; 50] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 10",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 11",
+    "4rrk1/pp1n3p/3q2pQ/2p1pb2/2PP4/2P3N1/P2B2PP/4RRK1 b - - 7 19",
+    "rq3rk1/ppp2ppp/1bnpb3/3N2B1/3NP3/7P/PPPQ1PP1/2KR3R w - - 7 14",
+    "r1bq1r1k/1pp1n1pp/1p1p4/4p2Q/4Pp2/1BNP4/PPP2PPP/3R1RK1 w - - 2 14",
+    "r3r1k1/2p2ppp/p1p1bn2/8/1q2P3/2NPQN2/PPP3PP/R4RK1 b - - 2 15",
+    "r1bbk1nr/pp3p1p/2n5/1N4p1/2Np1B2/8/PPP2PPP/2KR1B1R w kq - 0 13",
+    "r1bq1rk1/ppp1nppp/4n3/3p3Q/3P4/1BP1B3/PP1N2PP/R4RK1 w - - 1 16",
+    "4r1k1/r1q2ppp/ppp2n2/4P3/5Rb1/1N1BQ3/PPP3PP/R5K1 w - - 1 17",
+    "2rqkb1r/ppp2p2/2npb1p1/1N1Nn2p/2P1PP2/8/PP2B1PP/R1BQK2R b KQ - 0 11",
+    "r1bq1r1k/b1p1npp1/p2p3p/1p6/3PP3/1B2NN2/PP3PPP/R2Q1RK1 w - - 1 16",
+    "3r1rk1/p5pp/bpp1pp2/8/q1PP1P2/b3P3/P2NQRPP/1R2B1K1 b - - 6 22",
+    "r1q2rk1/2p1bppp/2Pp4/p6b/Q1PNp3/4B3/PP1R1PPP/2K4R w - - 2 18",
+    "4k2r/1pb2ppp/1p2p3/1R1p4/3P4/2r1PN2/P4PPP/1R4K1 b - - 3 22",
+    "3q2k1/pb3p1p/4pbp1/2r5/PpN2N2/1P2P2P/5PP1/Q2R2K1 b - - 4 26",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2",
+    "r1bqkbnr/pppp1ppp/2n5/1B2p3/4P3/5N2/PPPP1PPP/RNBQK2R b KQkq - 3 3",
+    "r1bqk1nr/pppp1ppp/2n5/2b1p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+    "rnbqkb1r/ppp2ppp/4pn2/3p4/2PP4/2N5/PP2PPPP/R1BQKBNR w KQkq - 2 4",
+    "rnbqkbnr/ppp2ppp/4p3/3p4/3PP3/8/PPP2PPP/RNBQKBNR w KQkq - 0 3",
+    "rnbqkbnr/pp2pppp/2p5/3p4/3PP3/8/PPP2PPP/RNBQKBNR w KQkq - 0 3",
+    "rnbq1rk1/ppp1ppbp/3p1np1/8/2PPP3/2N2N2/PP3PPP/R1BQKB1R w KQ - 1 6",
+    "r1bqkb1r/pp3ppp/2nppn2/8/3NP3/2N5/PPP2PPP/R1BQKB1R w KQkq - 0 6",
+    "rnbqk2r/pppp1ppp/4pn2/8/1bPP4/2N5/PP2PPPP/R1BQKBNR w KQkq - 2 4",
+    "r2qkb1r/pp1n1ppp/2p1pn2/3p1b2/2PP4/1QN1PN2/PP3PPP/R1B1KB1R w KQkq - 2 7",
+    "6k1/6p1/6Pp/ppp5/3pn2P/1P3K2/1PP2P2/8 b - - 0 1",
+    "6k1/3b3r/1p1p4/p1n2p2/1PPNpP1q/P3Q1p1/1R1RB1P1/5K2 b - - 0 1",
+    "r2r1n2/pp2bk2/2p1p2p/3q4/3PN1QP/2P3R1/P4PP1/5RK1 w - - 0 1",
+    "8/8/8/8/5kp1/P7/8/1K1N4 w - - 0 1",
+    "8/8/8/5N2/8/p7/8/2NK3k w - - 0 1",
+    "8/3k4/8/8/8/4B3/4KB2/2B5 w - - 0 1",
+    "8/8/1P6/5pr1/8/4R3/7k/2K5 w - - 0 1",
+    "8/2p4P/8/kr6/6R1/8/8/1K6 w - - 0 1",
+    "8/8/3P3k/8/1p6/8/1P6/1K3n2 b - - 0 1",
+    "8/R7/2q5/8/6k1/8/1P5p/K6R w - - 0 124",
+    "8/k7/3p4/p2P1p2/P2P1P2/8/8/K7 w - - 0 1",
+    "1K1k4/1P6/8/8/8/8/r7/2R5 w - - 0 1",
+    "4k3/r7/8/4PK2/8/8/8/1R6 b - - 0 1",
+    "8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1",
+    "8/8/8/3k4/8/8/3PK3/8 w - - 0 1",
+    "4k3/8/8/8/8/8/8/4KQ2 w - - 0 1",
+    "8/8/8/8/8/6k1/6p1/6K1 w - - 0 1",
+    "7k/7P/6K1/8/3B4/8/8/8 b - - 0 1",
+    "6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1",
+    "4k3/8/8/8/8/8/3q4/4K3 w - - 0 1",
+    "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1",
+];
+
+/// Default search depth.
+pub const DEPTH: u32 = 5;
+
+/// Result of a [bench] run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchReport {
+    pub positions: usize,
+    pub depth: u32,
+    /// The signature.
+    pub nodes: u64,
+    pub elapsed: Duration,
+}
+
+impl BenchReport {
+    /// Nodes per second.
+    pub fn nps(&self) -> u64 {
+        match self.elapsed.as_micros() {
+            0 => self.nodes,
+            micros => (self.nodes as u128 * 1_000_000 / micros) as u64,
+        }
+    }
+}
+
+impl core::fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Positions searched: {}", self.positions)?;
+        writeln!(f, "Depth             : {}", self.depth)?;
+        writeln!(f, "Total time (ms)   : {}", self.elapsed.as_millis())?;
+        writeln!(f, "Nodes searched    : {}", self.nodes)?;
+        write!(f, "Nodes/second      : {}", self.nps())
+    }
+}
+
+/// Searches each of `positions` to `depth` on one thread, with a `hash_mb`
+/// table. The searcher is cleared before every position, so the node count
+/// depends on nothing but the search itself. `progress` gets the index and
+/// result of every position searched.
+///
+/// # Panics
+///
+/// If one of `positions` isn't a valid FEN.
+pub fn bench(
+    positions: &[&str],
+    depth: u32,
+    hash_mb: usize,
+    mut progress: impl FnMut(usize, &SearchResult),
+) -> BenchReport {
+    let mut searcher = Searcher::new(hash_mb);
+    let limits = Limits {
+        depth: Some(depth),
+        ..Limits::default()
+    };
+
+    let start = Instant::now();
+    let mut nodes = 0;
+
+    for (i, fen) in positions.iter().enumerate() {
+        let board = Board::load_fen(fen.to_string()).expect("invalid bench position");
+
+        searcher.clear();
+        let result = searcher.search(&board, &[], &limits, &SearchControl::default(), |_| {});
+
+        nodes += result.nodes;
+        progress(i, &result);
+    }
+
+    BenchReport {
+        positions: positions.len(),
+        depth,
+        nodes,
+        elapsed: start.elapsed(),
+    }
+}
+
+#[cfg(test)]
+mod bench_tests {
+    use super::*;
+
+    #[test]
+    pub fn positions_are_valid() {
+        for fen in POSITIONS {
+            let board = Board::load_fen(fen.to_owned()).unwrap();
+
+            assert!(!board.in_check(board.turn.opponent()), "{}", fen);
+        }
+    }
+
+    #[test]
+    pub fn deterministic() {
+        let mut counts = vec![];
+        let first = bench(&POSITIONS[..6], 3, 1, |_, result| counts.push(result.nodes));
+
+        assert_eq!(first.positions, 6);
+        assert_eq!(first.nodes, counts.iter().sum::<u64>());
+        assert!(counts.iter().all(|&nodes| nodes > 0));
+
+        let second = bench(&POSITIONS[..6], 3, 1, |_, _| {});
+        assert_eq!(second.nodes, first.nodes);
+    }
+}
@@ -0,0 +1,384 @@
+//! Compares our move generation with another UCI engine's `go perft` divide
+//! and narrows a mismatch down to a single position, e.g.
+//!
+//! ```text
+//! perft-diff --depth 5 --fen "<fen>" stockfish
+//! ```
+
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    process::{Child, ChildStdin, ChildStdout, Command, ExitCode, Stdio},
+};
+
+use chess_engine::{
+    board::Board,
+    divide::{self, Divide},
+    uci::UciMove,
+};
+
+const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+const USAGE: &str = "usage: perft-diff [--depth N] [--fen FEN] [--chess960] ENGINE [ARGS...]";
+
+/// The engine to compare against, talked to over UCI.
+struct Reference<R, W> {
+    reader: R,
+    writer: W,
+}
+
+impl Reference<BufReader<ChildStdout>, ChildStdin> {
+    fn spawn(program: &str, args: &[String]) -> io::Result<(Child, Self)> {
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+
+        let reader = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        let writer = child.stdin.take().expect("stdin is piped");
+
+        Ok((child, Self::new(reader, writer)))
+    }
+}
+
+impl<R: BufRead, W: Write> Reference<R, W> {
+    fn new(reader: R, writer: W) -> Self {
+        Self { reader, writer }
+    }
+
+    fn send(&mut self, command: &str) -> io::Result<()> {
+        writeln!(self.writer, "{}", command)?;
+        self.writer.flush()
+    }
+
+    /// Reads lines up to and including the first one starting with `token`.
+    fn read_until(&mut self, token: &str) -> io::Result<Vec<String>> {
+        let mut lines = vec![];
+
+        loop {
+            let mut line = String::new();
+
+            if self.reader.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("engine quit while waiting for '{}'", token),
+                ));
+            }
+
+            let done = line.trim_start().starts_with(token);
+            lines.push(line);
+
+            if done {
+                return Ok(lines);
+            }
+        }
+    }
+
+    fn handshake(&mut self, chess960: bool) -> io::Result<()> {
+        self.send("uci")?;
+        self.read_until("uciok")?;
+
+        if chess960 {
+            self.send("setoption name UCI_Chess960 value true")?;
+        }
+
+        self.send("isready")?;
+        self.read_until("readyok")?;
+
+        Ok(())
+    }
+
+    fn divide(&mut self, fen: &str, path: &[UciMove], depth: u32) -> io::Result<Divide> {
+        let mut position = format!("position fen {}", fen);
+
+        if !path.is_empty() {
+            position.push_str(" moves");
+
+            for mv in path {
+                position.push_str(&format!(" {}", mv));
+            }
+        }
+
+        self.send(&position)?;
+        self.send(&format!("go perft {}", depth))?;
+
+        let output = self.read_until("Nodes searched")?;
+
+        Ok(divide::parse_divide(&output.concat()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Args {
+    depth: u32,
+    fen: String,
+    chess960: bool,
+    engine: String,
+    engine_args: Vec<String>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Option<Self> {
+        let (mut depth, mut fen, mut chess960) = (4, STARTING_FEN.to_owned(), false);
+
+        let engine = loop {
+            match args.next()?.as_str() {
+                "--depth" => depth = args.next()?.parse().ok()?,
+                "--fen" => fen = args.next()?,
+                "--chess960" => chess960 = true,
+                flag if flag.starts_with("--") => return None,
+                engine => break engine.to_owned(),
+            }
+        };
+
+        Some(Self {
+            depth,
+            fen,
+            chess960,
+            engine,
+            engine_args: args.collect(),
+        })
+    }
+}
+
+/// Runs the comparison, returning whether both engines agree.
+fn compare<R: BufRead, W: Write>(
+    reference: &mut Reference<R, W>,
+    args: &Args,
+    out: &mut impl Write,
+) -> Result<bool, String> {
+    let mut board = Board::load_fen(args.fen.clone()).map_err(|e| e.to_string())?;
+    board.chess960 = args.chess960;
+
+    reference
+        .handshake(args.chess960)
+        .map_err(|e| format!("handshake failed: {}", e))?;
+
+    let divergence = divide::find_divergence(&board, args.depth, |path, depth| {
+        reference.divide(&args.fen, path, depth)
+    })
+    .map_err(|e| format!("perft failed: {}", e))?;
+
+    let result = match &divergence {
+        Some(divergence) => writeln!(out, "{}", divergence),
+        None => writeln!(out, "no differences at depth {}", args.depth),
+    };
+
+    result.map_err(|e| e.to_string())?;
+
+    Ok(divergence.is_none())
+}
+
+fn main() -> ExitCode {
+    let Some(args) = Args::parse(std::env::args().skip(1)) else {
+        eprintln!("{}", USAGE);
+        return ExitCode::from(2);
+    };
+
+    let (mut child, mut reference) = match Reference::spawn(&args.engine, &args.engine_args) {
+        Ok(spawned) => spawned,
+        Err(e) => {
+            eprintln!("error: failed to start {}: {}", args.engine, e);
+            return ExitCode::from(2);
+        }
+    };
+
+    let result = compare(&mut reference, &args, &mut io::stdout());
+
+    // Best effort, the engine may already be gone.
+    let _ = reference.send("quit");
+    drop(reference);
+    let _ = child.wait();
+
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod perft_diff_tests {
+    use std::{
+        io::{pipe, PipeReader, PipeWriter},
+        thread::{self, JoinHandle},
+    };
+
+    use super::*;
+
+    /// A stand-in engine on the other end of a pipe. Every command starting
+    /// with one of the script's prefixes is answered with the matching reply,
+    /// anything else is ignored. Returns the commands it saw.
+    fn scripted(
+        script: Vec<(&'static str, String)>,
+    ) -> (
+        Reference<BufReader<PipeReader>, PipeWriter>,
+        JoinHandle<Vec<String>>,
+    ) {
+        let (command_reader, command_writer) = pipe().unwrap();
+        let (reply_reader, mut reply_writer) = pipe().unwrap();
+
+        let engine = thread::spawn(move || {
+            let mut seen = vec![];
+
+            for line in BufReader::new(command_reader).lines() {
+                let line = line.unwrap();
+
+                if line == "quit" {
+                    break;
+                }
+
+                if let Some((_, reply)) = script.iter().find(|(cmd, _)| line.starts_with(cmd)) {
+                    write!(reply_writer, "{}", reply).unwrap();
+                }
+
+                seen.push(line);
+            }
+
+            seen
+        });
+
+        (
+            Reference::new(BufReader::new(reply_reader), command_writer),
+            engine,
+        )
+    }
+
+    fn args(fen: &str, depth: u32) -> Args {
+        Args {
+            depth,
+            fen: fen.to_owned(),
+            chess960: false,
+            engine: "stand-in".to_owned(),
+            engine_args: vec![],
+        }
+    }
+
+    /// `go perft` output for `divide`, in Stockfish's layout.
+    fn perft_output(divide: &Divide) -> String {
+        let mut output = String::new();
+
+        for (mv, nodes) in divide {
+            output.push_str(&format!("{}: {}\n", mv, nodes));
+        }
+
+        let total = divide.iter().map(|(_, nodes)| nodes).sum::<u64>();
+        output + &format!("\nNodes searched: {}\n\n", total)
+    }
+
+    fn handshake() -> Vec<(&'static str, String)> {
+        vec![
+            ("uci", "id name stand-in\nuciok\n".to_owned()),
+            ("isready", "readyok\n".to_owned()),
+        ]
+    }
+
+    const FEN: &str = "4k3/8/8/8/8/8/8/4K2R w K - 0 1";
+
+    #[test]
+    pub fn agreement() {
+        let board = Board::load_fen(FEN.to_owned()).unwrap();
+
+        let mut script = handshake();
+        script.push(("go perft 2", perft_output(&divide::divide(&board, 2))));
+
+        let (mut reference, engine) = scripted(script);
+        let mut out = vec![];
+
+        assert_eq!(compare(&mut reference, &args(FEN, 2), &mut out), Ok(true));
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "no differences at depth 2\n"
+        );
+
+        reference.send("quit").unwrap();
+        assert_eq!(
+            engine.join().unwrap(),
+            vec![
+                "uci".to_owned(),
+                "isready".to_owned(),
+                format!("position fen {}", FEN),
+                "go perft 2".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn finds_missing_move() {
+        let board = Board::load_fen(FEN.to_owned()).unwrap();
+        let castle = UciMove::parse("e1g1").unwrap();
+
+        // The stand-in thinks the king can step into the rook's file after O-O.
+        let mut root = divide::divide(&board, 2);
+        root.iter_mut()
+            .filter(|(mv, _)| *mv == castle)
+            .for_each(|(_, nodes)| *nodes += 1);
+
+        let mut after = board.clone();
+        after
+            .do_move(after.uci_to_board_move(after.turn, castle).unwrap())
+            .unwrap();
+
+        let mut child = divide::divide(&after, 1);
+        child.push((UciMove::parse("e8f7").unwrap(), 1));
+
+        let mut script = handshake();
+        script.push(("go perft 2", perft_output(&root)));
+        script.push(("go perft 1", perft_output(&child)));
+
+        let (mut reference, engine) = scripted(script);
+        let mut out = vec![];
+
+        assert_eq!(compare(&mut reference, &args(FEN, 2), &mut out), Ok(false));
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "fen: 4k3/8/8/8/8/8/8/5RK1 b - - 1 1\n\
+             moves: e1g1\n\
+             depth: 1\n  \
+             missing e8f7\n"
+        );
+
+        reference.send("quit").unwrap();
+        assert!(engine
+            .join()
+            .unwrap()
+            .contains(&format!("position fen {} moves e1g1", FEN)));
+    }
+
+    #[test]
+    pub fn engine_quits() {
+        let (mut reference, engine) = scripted(vec![("uci", "uciok\n".to_owned())]);
+
+        reference.send("quit").unwrap();
+        engine.join().unwrap();
+
+        let result = compare(&mut reference, &args(FEN, 2), &mut vec![]);
+        assert!(result.unwrap_err().starts_with("handshake failed"));
+    }
+
+    #[test]
+    pub fn arguments() {
+        let parse = |args: &[&str]| Args::parse(args.iter().map(|arg| arg.to_string()));
+
+        assert_eq!(
+            parse(&["--depth", "5", "--chess960", "stockfish", "--flag"]),
+            Some(Args {
+                depth: 5,
+                fen: STARTING_FEN.to_owned(),
+                chess960: true,
+                engine: "stockfish".to_owned(),
+                engine_args: vec!["--flag".to_owned()],
+            })
+        );
+        assert_eq!(
+            parse(&["--fen", FEN, "sf"]).map(|args| args.fen),
+            Some(FEN.to_owned())
+        );
+        assert_eq!(parse(&["--depth", "x", "sf"]), None);
+        assert_eq!(parse(&["--verbose", "sf"]), None);
+        assert_eq!(parse(&[]), None);
+    }
+}
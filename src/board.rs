@@ -1,11 +1,16 @@
-use std::{cell::LazyCell, collections::HashMap, str::FromStr};
+use std::{cell::LazyCell, str::FromStr};
 
 use crate::{
-    bitboard::{self, BitBoard}, hardcoded_moves::KNIGHT_MOVES, r#move::{CastlingMethod, Move, MoveFlag}, piece::{self, Color, Piece}, square::*, uci::UciMove, utils::{self, print_bitboard}, Slider
+    bitboard::{self, BitBoard},
+    movegen,
+    piece::{self, Color, Piece},
+    r#move::{CastlingMethod, Move, MoveFlag, PackedMove},
+    square::*,
+    uci::UciMove,
 };
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
-pub struct BitBoards(pub [BitBoard; 10]);
+pub struct BitBoards(pub [BitBoard; 8]);
 
 impl BitBoards {
     pub fn all_pieces(&self, color: Option<Color>) -> BitBoard {
@@ -15,14 +20,6 @@ impl BitBoards {
         }
     }
 
-    /// Attacked/Defended BItboard for a [Color].
-    pub fn ad_bitboard(color: Color) -> usize {
-        match color {
-            Color::White => 8,
-            Color::Black => 9,
-        }
-    }
-
     pub fn get_piece_set<'a>(&'a self, piece: Piece, color: Option<Color>) -> BitBoard {
         match color {
             Some(color) => self.0[(color as u8) as usize] & self.0[(piece as u8) as usize],
@@ -30,13 +27,6 @@ impl BitBoards {
         }
     }
 
-    pub fn add_attackerd_bitboard(&self, color: Color) -> BitBoard {
-        match color {
-            Color::White => self.0[8],
-            Color::Black => self.0[9],
-        }
-    }
-
     pub fn sliding_pieces(&self, color: Color) -> BitBoard {
         self.get_piece_set(Piece::Bishop, Some(color))
             | self.get_piece_set(Piece::Queen, Some(color))
@@ -71,38 +61,174 @@ impl BitBoards {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+    /// There is no piece on the square the move needs one on.
+    EmptySquare(Square),
+    /// The [MoveFlag] doesn't fit the move, e.g. en passant without a pawn to take.
+    InvalidFlag(Move),
+    /// [Board::undo_move] without a matching [Board::do_move].
+    NothingToUndo,
+}
+
+impl core::fmt::Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EmptySquare(sq) => write!(f, "no piece on {}", sq),
+            Self::InvalidFlag(mv) => write!(f, "invalid move flag {:?} for {}", mv.flag, mv),
+            Self::NothingToUndo => f.write_str("no move to undo"),
+        }
+    }
+}
+
+impl std::error::Error for MoveError {}
+
+/// Why [Board::load_fen] rejected a FEN, each with the byte offset it was found at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FenError {
+    MissingField(usize, &'static str),
+    TooManyFields(usize),
+    InvalidPiece(usize, char),
+    /// The rank doesn't have exactly eight squares.
+    RankLength(usize, Rank),
+    /// The placement doesn't have exactly eight ranks.
+    RankCount(usize),
+    InvalidSideToMove(usize, String),
+    InvalidCastling(usize, char),
+    InvalidEnPassant(usize, String),
+    InvalidClock(usize, String),
+    KingCount(usize, Color, u32),
+    PawnOnBackRank(usize, Square),
+    /// No pawn could have just double-pushed past the en passant square.
+    InconsistentEnPassant(usize, Square),
+    /// A castling right without the king and rook on their starting squares.
+    CastlingWithoutPieces(usize, char),
+}
+
+impl FenError {
+    pub fn position(&self) -> usize {
+        match self {
+            Self::MissingField(p, _)
+            | Self::TooManyFields(p)
+            | Self::InvalidPiece(p, _)
+            | Self::RankLength(p, _)
+            | Self::RankCount(p)
+            | Self::InvalidSideToMove(p, _)
+            | Self::InvalidCastling(p, _)
+            | Self::InvalidEnPassant(p, _)
+            | Self::InvalidClock(p, _)
+            | Self::KingCount(p, _, _)
+            | Self::PawnOnBackRank(p, _)
+            | Self::InconsistentEnPassant(p, _)
+            | Self::CastlingWithoutPieces(p, _) => *p,
+        }
+    }
+}
+
+impl core::fmt::Display for FenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid FEN at {}: ", self.position())?;
+
+        match self {
+            Self::MissingField(_, field) => write!(f, "missing {} field", field),
+            Self::TooManyFields(_) => f.write_str("too many fields"),
+            Self::InvalidPiece(_, c) => write!(f, "unexpected '{}' in piece placement", c),
+            Self::RankLength(_, rank) => {
+                write!(f, "rank {} doesn't have 8 squares", *rank as usize + 1)
+            }
+            Self::RankCount(_) => f.write_str("piece placement doesn't have 8 ranks"),
+            Self::InvalidSideToMove(_, side) => write!(f, "invalid side to move \"{}\"", side),
+            Self::InvalidCastling(_, c) => write!(f, "invalid castling right '{}'", c),
+            Self::InvalidEnPassant(_, sq) => write!(f, "invalid en passant square \"{}\"", sq),
+            Self::InvalidClock(_, clock) => write!(f, "invalid move clock \"{}\"", clock),
+            Self::KingCount(_, color, n) => write!(f, "{:?} has {} kings", color, n),
+            Self::PawnOnBackRank(_, sq) => write!(f, "pawn on back rank square {}", sq),
+            Self::InconsistentEnPassant(_, sq) => {
+                write!(f, "no pawn could have moved past en passant square {}", sq)
+            }
+            Self::CastlingWithoutPieces(_, c) => {
+                write!(f, "castling right '{}' without king and rook in place", c)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+/// State [Board::do_move] can't recompute when undoing, saved per move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UndoState {
+    pub castling_availability: [(bool, bool); 2],
+    pub en_passant: BitBoard,
+    pub halfmove_count: usize,
+    /// Piece taken by a capturing promotion, which [MoveFlag::Promotion] doesn't carry.
+    pub captured: Option<Piece>,
+}
+
+/// How [Board::to_fen_with] writes the en passant field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FenEnPassant {
+    /// Only if a pawn can legally capture en passant.
+    #[default]
+    Capturable,
+    /// After every double pawn push.
+    Always,
+}
+
 #[derive(Debug, Clone)]
 pub struct Board {
     pub bitboards: BitBoards,
     pub turn: Color,
     pub castling_availability: [(bool, bool); 2],
+    /// Starting squares of the castling rooks, indexed by color and [CastlingMethod].
+    pub castling_rooks: [[Square; 2]; 2],
+    /// Chess960 mode, castling moves are written as king takes rook in UCI.
+    pub chess960: bool,
     pub en_passant: BitBoard,
-    pub en_passant_prev: BitBoard,
     pub halfmove_count: usize,
+    /// Fullmove number, incremented after Black's move.
     pub move_count: usize,
     pub squares: [Option<(Color, Piece)>; 64],
-    pub move_filters: [BitBoard; 2], // used for checks...
+    pub history: Vec<UndoState>,
+}
+
+/// Two boards are equal if they hold the same position, the same clocks and
+/// the same en passant rights. An en passant square no pawn can use is ignored.
+impl PartialEq for Board {
+    fn eq(&self, other: &Self) -> bool {
+        self.bitboards == other.bitboards
+            && self.turn == other.turn
+            && self.castling_rights() == other.castling_rights()
+            && self.halfmove_count == other.halfmove_count
+            && self.move_count == other.move_count
+            && self.en_passant_square() == other.en_passant_square()
+    }
+}
+
+impl Eq for Board {}
+
+impl core::fmt::Display for Board {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_fen())
+    }
 }
 
 impl Board {
     pub fn new() -> Self {
         Self {
-            bitboards: BitBoards([BitBoard(0b0u64); 10]),
+            bitboards: BitBoards([BitBoard(0b0u64); 8]),
             squares: [None; 64],
             turn: Color::White,
             castling_availability: [(false, false); 2],
+            castling_rooks: [[Square::H1, Square::A1], [Square::H8, Square::A8]],
+            chess960: false,
             en_passant: BitBoard::EMPTY,
-            en_passant_prev: BitBoard::EMPTY,
             halfmove_count: 0,
             move_count: 1,
-            move_filters: [BitBoard::EMPTY; 2],
+            history: vec![],
         }
     }
 
-    pub fn prepare(&mut self) {
-        _ = self.generate_moves(self.turn.opponent());
-    }
-
     pub fn count_material(&self) -> (u32, u32) {
         let mut white_material = 0;
         let mut black_material = 0;
@@ -186,32 +312,29 @@ impl Board {
         None
     }
 
-    pub fn undo_move(&mut self, mv: Move) -> Option<()> {
+    pub fn undo_move(&mut self, mv: Move) -> Result<(), MoveError> {
         //println!("DEBUG: UndoMove(mv = {:?}), Turn: {:?}", mv, self.turn.opponent());
 
-        _ = match self.get_piece_type(mv.target_square) {
-            None => {
-                print_bitboard(self.bitboards.all_pieces(None));
-                println!("Turn: {:?}", self.turn);
-                panic!();
+        if !matches!(
+            mv.flag,
+            MoveFlag::Castle(_) | MoveFlag::Untargeted | MoveFlag::NullMove
+        ) && self.get_piece_type(mv.target_square).is_none()
+        {
+            return Err(MoveError::EmptySquare(mv.target_square));
+        }
+
+        if let MoveFlag::EnPassant(other_pawn) = mv.flag {
+            if other_pawn != Square::new(mv.target_square.file(), mv.starting_square.rank()) {
+                return Err(MoveError::InvalidFlag(mv));
             }
-            Some(v) => v,
-        };
+        }
+
+        let state = self.history.pop().ok_or(MoveError::NothingToUndo)?;
 
         self.turn = self.turn.opponent();
 
         match mv.flag {
-            MoveFlag::EnPassant(_t) => {
-                let other_pawn = Square::index(
-                    mv.target_square
-                        .bitboard()
-                        .backward(self.turn)
-                        .0
-                        .trailing_zeros() as usize,
-                );
-
-                assert!(_t == other_pawn);
-
+            MoveFlag::EnPassant(other_pawn) => {
                 self.bitboards
                     .insert_piece(other_pawn, Piece::Pawn, self.turn.opponent());
 
@@ -221,94 +344,99 @@ impl Board {
             }
 
             MoveFlag::Capture(target) => {
-                self.undo_simple_move(self.get_piece_type(mv.target_square)?, mv);
+                self.undo_simple_move(
+                    self.get_piece_type(mv.target_square)
+                        .ok_or(MoveError::EmptySquare(mv.target_square))?,
+                    mv,
+                );
                 self.bitboards
                     .insert_piece(mv.target_square, target, self.turn.opponent());
                 self.squares[mv.target_square as usize] = Some((self.turn.opponent(), target));
             }
 
             MoveFlag::Promotion(target) => {
-                self.bitboards.0[target as usize].0 &= !mv.target_square.bitboard().0;
-                self.bitboards.0[Piece::Pawn as usize] |= mv.starting_square.bitboard();
+                self.bitboards
+                    .remove_piece(target, self.turn, mv.target_square);
+                self.bitboards
+                    .insert_piece(mv.starting_square, Piece::Pawn, self.turn);
+                self.squares[mv.target_square as usize] = None;
                 self.squares[mv.starting_square as usize] = Some((self.turn, Piece::Pawn));
+
+                if let Some(captured) = state.captured {
+                    self.bitboards
+                        .insert_piece(mv.target_square, captured, self.turn.opponent());
+                    self.squares[mv.target_square as usize] =
+                        Some((self.turn.opponent(), captured));
+                }
             }
             MoveFlag::Castle(method) => {
+                let rook_start = self.castling_rooks[self.turn as usize][method as usize];
                 let (king_target, rook_target) = Self::castling_squares(self.turn, method);
 
-                // King
-                self.undo_simple_move(
-                    Piece::King,
-                    Move {
-                        starting_square: Self::CASTLING_SQUARES[self.turn as usize]
-                            [method as usize]
-                            .0,
-                        target_square: king_target,
-                        flag: mv.flag,
-                    },
-                );
-
-                // Rook
-                self.undo_simple_move(
-                    Piece::Rook,
-                    Move {
-                        starting_square: Self::CASTLING_SQUARES[self.turn as usize]
-                            [method as usize]
-                            .1,
-                        target_square: rook_target,
-                        flag: mv.flag,
-                    },
+                self.place_castling_pieces(
+                    (king_target, rook_target),
+                    (mv.starting_square, rook_start),
                 );
             }
             MoveFlag::Untargeted => {}
             MoveFlag::NullMove => {}
-            _ => self.undo_simple_move(self.get_piece_type(mv.target_square)?, mv),
+            _ => self.undo_simple_move(
+                self.get_piece_type(mv.target_square)
+                    .ok_or(MoveError::EmptySquare(mv.target_square))?,
+                mv,
+            ),
         }
 
-        self.move_filters = [BitBoard::EMPTY; 2];
-
-        self.en_passant = self.en_passant_prev;
-        self.en_passant_prev = BitBoard::EMPTY;
-
-        self.move_count = self.move_count.saturating_sub(1);
+        self.castling_availability = state.castling_availability;
+        self.en_passant = state.en_passant;
+        self.halfmove_count = state.halfmove_count;
 
-        // halfmove clock!!
-
-        //  self.turn = self.turn.opponent();
+        if self.turn == Color::Black {
+            self.move_count = self.move_count.saturating_sub(1);
+        }
 
-        Some(())
+        Ok(())
     }
 
-    pub fn do_move(&mut self, mv: Move) -> Option<()> {
-        let piece = match self.get_piece_type(mv.starting_square) {
-            None => {
-                print_bitboard(self.bitboards.all_pieces(None));
-                println!("Turn: {:?}", self.turn);
-                panic!();
-            }
-            Some(v) => v,
+    pub fn do_move(&mut self, mv: Move) -> Result<(), MoveError> {
+        let piece = match mv.flag {
+            MoveFlag::Castle(_) | MoveFlag::Untargeted | MoveFlag::NullMove => Piece::King,
+            _ => self
+                .get_piece_type(mv.starting_square)
+                .ok_or(MoveError::EmptySquare(mv.starting_square))?,
         };
 
+        if let MoveFlag::EnPassant(other_pawn) = mv.flag {
+            if piece != Piece::Pawn
+                || other_pawn != Square::new(mv.target_square.file(), mv.starting_square.rank())
+            {
+                return Err(MoveError::InvalidFlag(mv));
+            }
+        }
+
+        self.history.push(UndoState {
+            castling_availability: self.castling_availability,
+            en_passant: self.en_passant,
+            halfmove_count: self.halfmove_count,
+            captured: self.get_piece_type(mv.target_square),
+        });
+
         if piece == Piece::Pawn && Self::is_double_move(self.turn, mv) {
             self.en_passant = mv.starting_square.bitboard().forward(self.turn);
         } else {
-            self.en_passant_prev = self.en_passant;
             self.en_passant = BitBoard::EMPTY;
         }
 
-        match mv.flag {
-            MoveFlag::EnPassant(_t) => {
-                assert!(piece == Piece::Pawn, "only pawns can do en passant!");
-
-                let other_pawn = Square::index(
-                    mv.target_square
-                        .bitboard()
-                        .backward(self.turn)
-                        .0
-                        .trailing_zeros() as usize,
-                );
+        match (piece, mv.flag) {
+            (_, MoveFlag::NullMove | MoveFlag::Untargeted) => self.halfmove_count += 1,
+            (Piece::Pawn, _) | (_, MoveFlag::Capture(_)) => self.halfmove_count = 0,
+            _ => self.halfmove_count += 1,
+        }
 
-                assert!(other_pawn == _t);
+        self.update_castling_availability(piece, mv);
 
+        match mv.flag {
+            MoveFlag::EnPassant(other_pawn) => {
                 self.bitboards
                     .remove_piece(Piece::Pawn, self.turn.opponent(), other_pawn);
 
@@ -325,35 +453,25 @@ impl Board {
             }
 
             MoveFlag::Promotion(target) => {
-                self.bitboards.0[piece as usize].0 &= !mv.starting_square.bitboard().0;
-                self.bitboards.0[target as usize].0 |= mv.target_square.bitboard().0;
+                if let Some((_, captured)) = self.squares[mv.target_square as usize] {
+                    self.bitboards
+                        .remove_piece(captured, self.turn.opponent(), mv.target_square);
+                }
+
+                self.bitboards
+                    .remove_piece(piece, self.turn, mv.starting_square);
+                self.bitboards
+                    .insert_piece(mv.target_square, target, self.turn);
+                self.squares[mv.starting_square as usize] = None;
                 self.squares[mv.target_square as usize] = Some((self.turn, target));
             }
             MoveFlag::Castle(method) => {
+                let rook_start = self.castling_rooks[self.turn as usize][method as usize];
                 let (king_target, rook_target) = Self::castling_squares(self.turn, method);
 
-                // King
-                self.do_simple_move(
-                    Piece::King,
-                    Move {
-                        starting_square: Self::CASTLING_SQUARES[self.turn as usize]
-                            [method as usize]
-                            .0,
-                        target_square: king_target,
-                        flag: mv.flag,
-                    },
-                );
-
-                // Rook
-                self.do_simple_move(
-                    Piece::Rook,
-                    Move {
-                        starting_square: Self::CASTLING_SQUARES[self.turn as usize]
-                            [method as usize]
-                            .1,
-                        target_square: rook_target,
-                        flag: mv.flag,
-                    },
+                self.place_castling_pieces(
+                    (mv.starting_square, rook_start),
+                    (king_target, rook_target),
                 );
             }
             MoveFlag::Untargeted => {}
@@ -361,450 +479,698 @@ impl Board {
             _ => self.do_simple_move(piece, mv),
         }
 
-        self.move_filters = [BitBoard::EMPTY; 2];
-        self.bitboards.0[BitBoards::ad_bitboard(self.turn.opponent())] = BitBoard::EMPTY;
-
-        self.move_count += 1;
-
-        // halfmove clock!!
+        if self.turn == Color::Black {
+            self.move_count += 1;
+        }
 
         //    println!("Move  {}:", mv);
         //       print_bitboard(self.bitboards.all_pieces(None));
 
         self.turn = self.turn.opponent();
 
-        Some(())
+        Ok(())
     }
 
-    pub fn uci_to_board_move(&self, color: Color, mv: UciMove) -> Option<Move> {
-        let piece = self.get_piece_type(mv.starting_square)?;
-
-
-        if piece == Piece::Pawn && (mv.target_square.rank() == color.promotion_rank()) {
-            return Some(Move {
-                starting_square: mv.starting_square,
-                target_square: mv.target_square,
-                flag: MoveFlag::Promotion(mv.promotion?),
-            });
-        }
-
-        if piece == Piece::Pawn
-            && (mv.target_square.bitboard() & self.en_passant != BitBoard::EMPTY)
-        {
-            return Some(Move {
-                starting_square: mv.starting_square,
-                target_square: mv.target_square,
-                flag: MoveFlag::EnPassant(Square::index(
-                    mv.target_square.bitboard().backward(self.turn).0.trailing_zeros() as usize,
-                )),
-            });
+    /// Moving the king or a rook, or capturing a rook on its starting square, loses the right.
+    fn update_castling_availability(&mut self, piece: Piece, mv: Move) {
+        if matches!(mv.flag, MoveFlag::NullMove | MoveFlag::Untargeted) {
+            return;
         }
 
-        'capture: {if (mv.target_square.bitboard() & self.bitboards.all_pieces(Some(color.opponent())))
-            != BitBoard::EMPTY
-        {
-            if self.squares[mv.target_square as usize].is_none() {
-                break 'capture;
-            }
-
-            return Some(Move {
-                starting_square: mv.starting_square,
-                target_square: mv.target_square,
-                flag: MoveFlag::Capture(self.squares[mv.target_square as usize].unwrap().1),
-            });
-
-        } }
-
-        return Some(Move {
-            starting_square: mv.starting_square,
-            target_square: mv.target_square,
-            flag: MoveFlag::None,
-        });
-    }
-
-    pub fn generate_moves(&mut self, color: Color) -> Vec<Move> {
-        let mut move_bitboards: HashMap<Square, BitBoard> = HashMap::new();
-        let mut out = vec![];
-
-        let pawns = self.bitboards.get_piece_set(Piece::Pawn, Some(color));
-
-        let king_moves = self.king_moves(color) & !self.pieces(color);
-
-        self.bitboards.0[BitBoards::ad_bitboard(color)] |= king_moves;
-        move_bitboards.insert(self.king_square(color), king_moves);
-
-        let (pinned, checkers);
-
-        (pinned, checkers) = self.pinned_pieces(color);
-
-        for pawn in Self::isolate_pieces(pawns) {
-            let square = Square::index(pawn.0.trailing_zeros() as _);
-
-            let (ad, moves) = self.pawn_moves(pawn, color);
-            move_bitboards.insert(square, moves & !self.pieces(color));
-            self.bitboards.0[BitBoards::ad_bitboard(color)] |= ad;
+        if piece == Piece::King {
+            self.castling_availability[self.turn as usize] = (false, false);
         }
 
-        for piece in Piece::SLIDING {
-            for piece_board in
-                Self::isolate_pieces(self.bitboards.get_piece_set(piece, Some(color)))
-            {
-                let square = Square::index(piece_board.0.trailing_zeros() as _);
-
-                let relevant_blockers =
-                    self.bitboards.all_pieces(None) & piece.possible_moves(square);
-
-                let mut moves;
-
-                /*if piece == Piece::Rook {
-                    eprintln!("DEBUG: Generating Rook Moves!");
-                    moves =
-                        self.hacky_rook_fix_moves(color, square, piece_board.0, relevant_blockers)
-                } else {*/
-                moves = self.slider_moves(
-                    piece
-                        .sliders()
-                        .expect("Tried to query sliders moves for a non-slider piece"),
-                    color,
-                    piece_board.0,
-                    relevant_blockers,
-                );
-                //}
+        let touched = mv.starting_square.bitboard() | mv.target_square.bitboard();
 
-                moves = (moves & !self.pieces(color)) & piece.possible_moves(square);
+        for color in [Color::White, Color::Black] {
+            let [short, long] = self.castling_rooks[color as usize];
 
-                move_bitboards.insert(square, moves);
+            if touched & short.bitboard() != BitBoard::EMPTY {
+                self.castling_availability[color as usize].0 = false;
+            }
 
-                self.bitboards.0[BitBoards::ad_bitboard(color)] |= moves; // Should this be a side-effect or not?
+            if touched & long.bitboard() != BitBoard::EMPTY {
+                self.castling_availability[color as usize].1 = false;
             }
         }
+    }
 
-        for knight in Self::isolate_pieces(self.bitboards.get_piece_set(Piece::Knight, Some(color)))
-        {
-            let sq = Square::index(knight.0.trailing_zeros() as _);
-
-            let moves = KNIGHT_MOVES[sq as usize];
+    /// Moves the castling king and rook of the side to move. Both leave their
+    /// squares before either lands, as the squares can overlap in Chess960.
+    fn place_castling_pieces(&mut self, from: (Square, Square), to: (Square, Square)) {
+        let color = self.turn;
 
-            self.bitboards.0[BitBoards::ad_bitboard(color)] |= moves;
+        self.bitboards.remove_piece(Piece::King, color, from.0);
+        self.bitboards.remove_piece(Piece::Rook, color, from.1);
+        self.squares[from.0 as usize] = None;
+        self.squares[from.1 as usize] = None;
 
-            move_bitboards.insert(sq, moves);
-        }
+        self.bitboards.insert_piece(to.0, Piece::King, color);
+        self.bitboards.insert_piece(to.1, Piece::Rook, color);
+        self.squares[to.0 as usize] = Some((color, Piece::King));
+        self.squares[to.1 as usize] = Some((color, Piece::Rook));
+    }
 
-        if self.can_castle_short(color) && !self.in_check(color) {
-            out.push(Move {
-                starting_square: self.king_square(color),
-                target_square: Self::castling_squares(color, CastlingMethod::Short).0,
-                flag: MoveFlag::Castle(CastlingMethod::Short),
-            });
+    /// The en passant square if the side to move can legally capture on it.
+    pub fn en_passant_square(&self) -> Option<Square> {
+        if self.en_passant == BitBoard::EMPTY {
+            return None;
         }
 
-        if self.can_castle_long(color) & !self.in_check(color) {
-            out.push(Move {
-                starting_square: self.king_square(color),
-                target_square: Self::castling_squares(color, CastlingMethod::Long).1,
-                flag: MoveFlag::Castle(CastlingMethod::Long),
-            });
-        }
+        let target = Square::index(self.en_passant.0.trailing_zeros() as usize);
+        let other_pawn = target.try_offset(
+            0,
+            match self.turn {
+                Color::White => -1,
+                Color::Black => 1,
+            },
+        )?;
+
+        let attackers = movegen::pawn_attacks(target, self.turn.opponent())
+            & self.bitboards.get_piece_set(Piece::Pawn, Some(self.turn));
+
+        attackers
+            .active_squares()
+            .into_iter()
+            .any(|from| {
+                self.is_legal(Move {
+                    starting_square: from,
+                    target_square: target,
+                    flag: MoveFlag::EnPassant(other_pawn),
+                })
+            })
+            .then_some(target)
+    }
 
-        let mut move_bitboards_v = move_bitboards.into_iter().collect::<Vec<_>>();
+    pub fn to_fen(&self) -> String {
+        self.to_fen_with(FenEnPassant::default())
+    }
 
-        move_bitboards_v.sort_by_key(|f| f.0);
+    /// Six-field FEN of the position.
+    pub fn to_fen_with(&self, en_passant: FenEnPassant) -> String {
+        let mut fen = String::with_capacity(90);
 
-        'conv: for (sq, mut bitboard) in move_bitboards_v {
-            //println!("moves for piece on {}: {}", sq,bitboard.0.count_ones());
+        for rank in Rank::ALL.iter().rev() {
+            let mut empty = 0;
 
-            /*if sq == Square::A6 {
-                print_bitboard(bitboard);
-            }*/
+            for file in File::ALL {
+                match self.squares[Square::new(file, *rank) as usize] {
+                    Some((color, piece)) => {
+                        if empty != 0 {
+                            fen.push(char::from_digit(empty, 10).unwrap());
+                            empty = 0;
+                        }
 
-            if sq.bitboard() & self.pieces(color.opponent()) != BitBoard::EMPTY {
-                // eprintln!("BUG: Tried to to move an opponent's piece on {}", sq);
-                continue;
+                        fen.push(match color {
+                            Color::White => piece.notation(),
+                            Color::Black => piece.notation().to_ascii_lowercase(),
+                        });
+                    }
+                    None => empty += 1,
+                }
             }
 
-            if pinned.contains_key(&sq) {
-                continue 'conv;
+            if empty != 0 {
+                fen.push(char::from_digit(empty, 10).unwrap());
             }
 
-            for target_sq in bitboard.active_squares() {
-                if target_sq.bitboard() & self.pieces(color) != BitBoard::EMPTY {
-                    //  eprintln!("BUG: Tried to capture a same-colored piece {}x{}.", sq, target_sq);
-                    continue;
-                }
-
-                let piece = self.get_piece_type(sq).expect("failed to get piece type.");
-
-                if (piece == Piece::Rook && sq.file() == File::H)
-                    && self.castling_availability[color as usize].0
-                {
-                    self.castling_availability[color as usize].0 = false;
-                }
-
-                if (piece == Piece::Rook && sq.file() == File::A)
-                    && self.castling_availability[color as usize].1
-                {
-                    self.castling_availability[color as usize].1 = false;
-                }
+            if *rank != Rank::First {
+                fen.push('/');
+            }
+        }
 
-                if piece == Piece::King && sq == Self::CASTLING_SQUARES[color as usize][0].0 {
-                    self.castling_availability[color as usize] = (false, false);
-                }
+        fen.push_str(match self.turn {
+            Color::White => " w ",
+            Color::Black => " b ",
+        });
 
-                if self.in_check(color) {
-                    let (checks, rays) = checkers;
-                    if checks == 1 && piece != Piece::King {
-                        if (target_sq.bitboard() & rays).0 == 0 {
-                            /*println!(
-                                "Filtered Move: {}",
-                                Move {
-                                    starting_square: sq,
-                                    target_square: target_sq,
-                                    flag: MoveFlag::None,
-                                }
-                            );*/
-                            continue;
-                        }
-                    } else if checks > 1 && piece != Piece::King {
-                        /*println!(
-                            "Filtered Move: {}",
-                            Move {
-                                starting_square: sq,
-                                target_square: target_sq,
-                                flag: MoveFlag::None,
-                            }
-                        );*/
-                        continue 'conv;
-                    }
-                }
+        let rights = [Color::White, Color::Black]
+            .into_iter()
+            .flat_map(|color| {
+                [CastlingMethod::Short, CastlingMethod::Long]
+                    .into_iter()
+                    .filter_map(move |method| self.castling_notation(color, method))
+            })
+            .collect::<String>();
+
+        match rights.is_empty() {
+            true => fen.push('-'),
+            false => fen.push_str(&rights),
+        }
 
-                if piece == Piece::Pawn && (target_sq.rank() == color.promotion_rank()) {
-                    for promotion in Piece::PROMOTIONS {
-                        out.push(Move {
-                            starting_square: sq,
-                            target_square: target_sq,
-                            flag: MoveFlag::Promotion(promotion),
-                        });
-                    }
+        let en_passant = match en_passant {
+            FenEnPassant::Capturable => self.en_passant_square(),
+            FenEnPassant::Always => match self.en_passant {
+                BitBoard::EMPTY => None,
+                ep => Some(Square::index(ep.0.trailing_zeros() as usize)),
+            },
+        };
 
-                    continue;
-                }
+        match en_passant {
+            Some(square) => fen.push_str(&format!(" {} ", square.to_string().to_ascii_lowercase())),
+            None => fen.push_str(" - "),
+        }
 
-                if piece == Piece::Pawn
-                    && (target_sq.bitboard() & self.en_passant != BitBoard::EMPTY)
-                {
-                    out.push(Move {
-                        starting_square: sq,
-                        target_square: target_sq,
-                        flag: MoveFlag::EnPassant(Square::index(
-                            target_sq.bitboard().backward(self.turn).0.trailing_zeros() as usize,
-                        )),
-                    });
+        fen.push_str(&format!("{} {}", self.halfmove_count, self.move_count));
 
-                    continue;
-                }
+        fen
+    }
 
-                if (target_sq.bitboard() & self.bitboards.all_pieces(Some(color.opponent())))
-                    != BitBoard::EMPTY
-                {
-                    if self.squares[target_sq as usize].is_none() {
-                        continue;
-                    }
+    /// The position drawn in ASCII, White at the bottom and pieces as in FEN.
+    pub fn to_ascii(&self) -> String {
+        const SEPARATOR: &str = " +---+---+---+---+---+---+---+---+\n";
 
-                    out.push(Move {
-                        starting_square: sq,
-                        target_square: target_sq,
-                        flag: MoveFlag::Capture(self.squares[target_sq as usize].unwrap().1),
-                    });
+        let mut ascii = SEPARATOR.to_owned();
 
-                    continue;
-                }
+        for rank in Rank::ALL.iter().rev() {
+            for file in File::ALL {
+                let c = match self.squares[Square::new(file, *rank) as usize] {
+                    Some((Color::White, piece)) => piece.notation(),
+                    Some((Color::Black, piece)) => piece.notation().to_ascii_lowercase(),
+                    None => ' ',
+                };
 
-                out.push(Move {
-                    starting_square: sq,
-                    target_square: target_sq,
-                    flag: MoveFlag::None,
-                });
+                ascii.push_str(&format!(" | {}", c));
             }
-        }
-
-        out
-    }
 
-    pub fn do_str_moves(&mut self, moves: &str) {
-        for mv in moves.split_whitespace() {
-            let start = Square::from_str(&mv[0..=1]).unwrap();
-            let target = Square::from_str(&mv[2..=3]).unwrap();
+            ascii.push_str(&format!(" | {}\n", *rank as u8 + 1));
+            ascii.push_str(SEPARATOR);
         }
-    }
 
-    pub fn isolate_pieces(board: BitBoard) -> Vec<BitBoard> {
-        let mut list = vec![];
-        let mut board = board;
-        while board.0 != 0 {
-            let e = BitBoard(board.0 & board.0.wrapping_neg());
-            list.push(e);
-            board.0 ^= e.0;
-        }
+        ascii.push_str("   a   b   c   d   e   f   g   h\n");
 
-        list
+        ascii
     }
 
-    pub fn blockers(&self) -> BitBoard {
-        self.bitboards.all_pieces(None)
+    /// Pieces giving check to the side to move.
+    pub fn checkers(&self) -> BitBoard {
+        self.attackers_of(
+            self.king_square(self.turn),
+            self.blockers(),
+            self.turn.opponent(),
+        )
     }
 
-    /// Possible moves for a isolated pawn with the given bitboard and color.
-    pub fn pawn_moves(&self, pawn: BitBoard, color: Color) -> (BitBoard, BitBoard) {
-        assert!(pawn.is_single());
-
-        // println!("Generating pawn  moves"); // 100ns -> 1µs of avg. time per node in perft()....
-
-        let square = Square::index(pawn.0.trailing_zeros() as _);
+    /// The same position with the colors swapped: the board upside down,
+    /// pieces, rights and the side to move changing hands.
+    pub fn mirrored(&self) -> Self {
+        let fen = self.to_fen_with(FenEnPassant::Always);
+        let fields = fen.split(' ').collect::<Vec<_>>();
 
-        let mut moves = pawn.forward(color) & !self.blockers();
+        let swap_case = |c: char| match c.is_ascii_uppercase() {
+            true => c.to_ascii_lowercase(),
+            false => c.to_ascii_uppercase(),
+        };
 
-        if (color == Color::White && square.rank() == Rank::Second)
-            || (color == Color::Black && square.rank() == Rank::Seventh)
-        {
-            moves |= pawn.forward(color).forward(color)
-                & !(self.blockers() | (self.blockers() & square.file().bitboard()).forward(color))
-        }
+        let placement = fields[0]
+            .split('/')
+            .rev()
+            .map(|rank| rank.chars().map(swap_case).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("/");
 
-        let capture_mask: BitBoard = match square.file() {
-            File::A => pawn.forward(color).shl(1),
-            File::H => pawn.forward(color).shr(1),
-            _ => pawn.forward(color).shl(1) | pawn.forward(color).shr(1),
+        let turn = match self.turn {
+            Color::White => "b",
+            Color::Black => "w",
         };
 
-        moves |= self.bitboards.all_pieces(Some(color.opponent())) & capture_mask;
-        moves |= self.en_passant & capture_mask;
-
-        (capture_mask, moves)
+        // White's rights come first.
+        let (white, black): (String, String) = fields[2]
+            .chars()
+            .map(swap_case)
+            .partition(|c| c.is_ascii_uppercase());
+
+        let en_passant = fields[3]
+            .replace('3', "_")
+            .replace('6', "3")
+            .replace('_', "6");
+
+        let mut board = Self::load_fen(format!(
+            "{} {} {}{} {} {} {}",
+            placement, turn, white, black, en_passant, fields[4], fields[5]
+        ))
+        .expect("mirrored position is invalid");
+        board.chess960 = self.chess960;
+
+        board
     }
 
-    // slider_moves() doesn't seem to work with the rook for
-    // some reason so for now we just have this hacky fix of reusing the old implementation.
-    fn hacky_rook_fix_moves(
-        &self,
-        color: Color,
-        square: Square,
-        start_position: u64,
-        relevant_blockers: BitBoard,
-    ) -> BitBoard {
-        let _ = color;
-        let mut out = BitBoard::EMPTY;
-        let rank_mask = square.rank().bitboard().0; // Create a mask for the rank
-        let rank = square.rank();
+    /// X-FEN letter of a castling right: `KQkq` for the outermost rook on that
+    /// side of the king, otherwise the rook's file as in Shredder-FEN.
+    fn castling_notation(&self, color: Color, method: CastlingMethod) -> Option<char> {
+        let rook = self.castling_rook(color, method)?;
 
-        let mut position = start_position;
-        let mut steps = 0;
+        let outer_files = match method {
+            CastlingMethod::Short => rook.file() as usize + 1..8,
+            CastlingMethod::Long => 0..rook.file() as usize,
+        };
 
-        // LEFT
-        while position != 0 {
-            steps += 1;
+        let outermost = outer_files.into_iter().all(|file| {
+            self.squares[Square::new(File::index(file), rook.rank()) as usize]
+                != Some((color, Piece::Rook))
+        });
 
-            position = position >> 1;
-            if position & relevant_blockers.0 != 0 {
-                break;
-            }
+        let c = match (outermost, method) {
+            (true, CastlingMethod::Short) => 'K',
+            (true, CastlingMethod::Long) => 'Q',
+            (false, _) => (b'A' + rook.file() as u8) as char,
+        };
 
-            out |= BitBoard(position);
+        Some(match color {
+            Color::White => c,
+            Color::Black => c.to_ascii_lowercase(),
+        })
+    }
 
-            if position & BitBoard::CORNERS.0 != 0 {
-                break;
-            }
-        }
+    /// Starting square of the rook `color` may still castle with.
+    pub fn castling_rook(&self, color: Color, method: CastlingMethod) -> Option<Square> {
+        let available = match method {
+            CastlingMethod::Short => self.castling_availability[color as usize].0,
+            CastlingMethod::Long => self.castling_availability[color as usize].1,
+        };
 
-        steps = 0;
-        position = start_position;
+        available.then_some(self.castling_rooks[color as usize][method as usize])
+    }
 
-        // RIGHT
-        while position != 0 {
-            steps += 1;
+    /// Rooks of all castling rights, indexed by color and [CastlingMethod].
+    pub fn castling_rights(&self) -> [[Option<Square>; 2]; 2] {
+        [Color::White, Color::Black].map(|color| {
+            [CastlingMethod::Short, CastlingMethod::Long]
+                .map(|method| self.castling_rook(color, method))
+        })
+    }
 
-            position = position << 1;
-            if position & relevant_blockers.0 != 0 {
-                break;
+    /// The UCI form of `mv`, castling is written as king takes rook in Chess960 mode.
+    pub fn board_to_uci_move(&self, mv: Move) -> UciMove {
+        let target_square = match mv.flag {
+            MoveFlag::Castle(method) if self.chess960 => {
+                self.castling_rooks[self.turn as usize][method as usize]
             }
+            _ => mv.target_square,
+        };
 
-            out |= BitBoard(position);
-
-            if position & BitBoard::CORNERS.0 != 0 {
-                break;
-            }
+        UciMove {
+            starting_square: mv.starting_square,
+            target_square,
+            promotion: match mv.flag {
+                MoveFlag::Promotion(piece) => Some(piece),
+                _ => None,
+            },
         }
+    }
 
-        position = start_position;
-
-        // UP
-        while position != 0 {
-            position = position << 8;
-            if position & relevant_blockers.0 != 0 {
-                break;
-            }
-
-            out |= BitBoard(position);
+    /// Castling is accepted as king takes own rook in both modes and as the
+    /// two-square king move only outside Chess960 mode.
+    pub fn uci_to_board_move(&self, color: Color, mv: UciMove) -> Option<Move> {
+        let piece = self.get_piece_type(mv.starting_square)?;
 
-            if position & (Rank::Eighth.bitboard() & Rank::First.bitboard()).0 != 0 {
-                break;
+        if piece == Piece::King {
+            for method in [CastlingMethod::Short, CastlingMethod::Long] {
+                let king_target = Self::castling_squares(color, method).0;
+                let file_distance =
+                    (mv.starting_square.file() as i8 - mv.target_square.file() as i8).abs();
+
+                let takes_rook = self.castling_rook(color, method) == Some(mv.target_square)
+                    && self.squares[mv.target_square as usize] == Some((color, Piece::Rook));
+                let two_squares = !self.chess960
+                    && self.castling_rook(color, method).is_some()
+                    && mv.target_square == king_target
+                    && file_distance == 2;
+
+                if takes_rook || two_squares {
+                    return Some(Move {
+                        starting_square: mv.starting_square,
+                        target_square: king_target,
+                        flag: MoveFlag::Castle(method),
+                    });
+                }
             }
         }
 
-        position = start_position;
-
-        // DOWN
-        while position != 0 {
-            position = position >> 8;
-            if position & relevant_blockers.0 != 0 {
-                break;
+        if piece == Piece::Pawn && (mv.target_square.rank() == color.promotion_rank()) {
+            return Some(Move {
+                starting_square: mv.starting_square,
+                target_square: mv.target_square,
+                flag: MoveFlag::Promotion(mv.promotion?),
+            });
+        }
+
+        if piece == Piece::Pawn
+            && (mv.target_square.bitboard() & self.en_passant != BitBoard::EMPTY)
+        {
+            return Some(Move {
+                starting_square: mv.starting_square,
+                target_square: mv.target_square,
+                flag: MoveFlag::EnPassant(Square::index(
+                    mv.target_square
+                        .bitboard()
+                        .backward(self.turn)
+                        .0
+                        .trailing_zeros() as usize,
+                )),
+            });
+        }
+
+        'capture: {
+            if (mv.target_square.bitboard() & self.bitboards.all_pieces(Some(color.opponent())))
+                != BitBoard::EMPTY
+            {
+                if self.squares[mv.target_square as usize].is_none() {
+                    break 'capture;
+                }
+
+                return Some(Move {
+                    starting_square: mv.starting_square,
+                    target_square: mv.target_square,
+                    flag: MoveFlag::Capture(self.squares[mv.target_square as usize].unwrap().1),
+                });
             }
+        }
+
+        return Some(Move {
+            starting_square: mv.starting_square,
+            target_square: mv.target_square,
+            flag: MoveFlag::None,
+        });
+    }
+
+    /// Recover the full [Move] from a [PackedMove], `None` if a capture has nothing to capture.
+    pub fn unpack_move(&self, mv: PackedMove) -> Option<Move> {
+        let (starting_square, target_square) = (mv.starting_square(), mv.target_square());
+
+        let flag = match mv.flag() {
+            PackedMove::FLAG_NONE => MoveFlag::None,
+            PackedMove::FLAG_CASTLE_SHORT => MoveFlag::Castle(CastlingMethod::Short),
+            PackedMove::FLAG_CASTLE_LONG => MoveFlag::Castle(CastlingMethod::Long),
+            PackedMove::FLAG_NULL_MOVE => MoveFlag::NullMove,
+            PackedMove::FLAG_CAPTURE => MoveFlag::Capture(self.squares[target_square as usize]?.1),
+            PackedMove::FLAG_UNTARGETED => MoveFlag::Untargeted,
+            PackedMove::FLAG_EN_PASSANT => {
+                MoveFlag::EnPassant(Square::new(target_square.file(), starting_square.rank()))
+            }
+            _ => MoveFlag::Promotion(mv.promotion()?),
+        };
 
-            out |= BitBoard(position);
+        Some(Move {
+            starting_square,
+            target_square,
+            flag,
+        })
+    }
+
+    /// Pieces of `color` attacking `square`, with sliders blocked by `occupancy`.
+    fn attackers_of(&self, square: Square, occupancy: BitBoard, color: Color) -> BitBoard {
+        let pieces = |piece| self.bitboards.get_piece_set(piece, Some(color));
+
+        (movegen::pawn_attacks(square, color.opponent()) & pieces(Piece::Pawn))
+            | (movegen::knight_attacks(square) & pieces(Piece::Knight))
+            | (movegen::king_attacks(square) & pieces(Piece::King))
+            | (movegen::bishop_attacks(square, occupancy)
+                & (pieces(Piece::Bishop) | pieces(Piece::Queen)))
+            | (movegen::rook_attacks(square, occupancy)
+                & (pieces(Piece::Rook) | pieces(Piece::Queen)))
+    }
+
+    /// Pieces of both colors attacking `square`, with sliders blocked by `occupancy`.
+    ///
+    /// Passing a modified occupancy allows x-ray queries, e.g. with a moving piece removed.
+    pub fn attackers_to(&self, square: Square, occupancy: BitBoard) -> BitBoard {
+        self.attackers_of(square, occupancy, Color::White)
+            | self.attackers_of(square, occupancy, Color::Black)
+    }
+
+    /// Whether the (legal) move `mv` puts the opponent in check, directly or
+    /// by discovering a slider. Castling checks come from the rook.
+    pub fn gives_check(&self, mv: Move) -> bool {
+        let us = self.turn;
+        let king = self.king_square(us.opponent());
+        let (from, to) = (mv.starting_square, mv.target_square);
+
+        let mut occupancy = self.blockers().0 & !from.bitboard().0;
+        let mut vacated = from.bitboard().0;
 
-            if position & BitBoard::CORNERS.0 != 0 {
-                break;
+        let (piece, square) = match mv.flag {
+            MoveFlag::Castle(method) => {
+                let rook_start = self.castling_rooks[us as usize][method as usize];
+                let (king_target, rook_target) = Self::castling_squares(us, method);
+
+                occupancy &= !rook_start.bitboard().0;
+                occupancy |= king_target.bitboard().0 | rook_target.bitboard().0;
+                vacated |= rook_start.bitboard().0;
+
+                (Piece::Rook, rook_target)
             }
+            MoveFlag::NullMove | MoveFlag::Untargeted => return false,
+            flag => {
+                if let MoveFlag::EnPassant(other_pawn) = flag {
+                    occupancy &= !other_pawn.bitboard().0;
+                }
+
+                occupancy |= to.bitboard().0;
+
+                let piece = match flag {
+                    MoveFlag::Promotion(promotion) => promotion,
+                    _ => match self.get_piece_type(from) {
+                        Some(piece) => piece,
+                        None => return false,
+                    },
+                };
+
+                (piece, to)
+            }
+        };
+
+        let occupancy = BitBoard(occupancy);
+
+        if movegen::piece_attacks(piece, us, square, occupancy) & king.bitboard() != BitBoard::EMPTY
+        {
+            return true;
         }
 
-        out & BitBoard(!start_position)
+        let ours = |piece| self.bitboards.get_piece_set(piece, Some(us)) & BitBoard(!vacated);
+
+        let discovered = (movegen::bishop_attacks(king, occupancy)
+            & (ours(Piece::Bishop) | ours(Piece::Queen)))
+            | (movegen::rook_attacks(king, occupancy) & (ours(Piece::Rook) | ours(Piece::Queen)));
+
+        discovered != BitBoard::EMPTY
     }
 
-    pub fn slider_moves(
-        &self,
-        sliders: &[crate::piece::Slider],
-        color: Color,
-        start_position: u64,
-        relevant_blockers: BitBoard,
-    ) -> BitBoard {
-        let mut out = BitBoard::EMPTY;
+    /// Checks that `mv` can be played by the side to move if we ignore checks,
+    /// without generating the full move list. Used for moves coming from
+    /// search tables that might belong to another position.
+    pub fn is_pseudo_legal(&self, mv: Move) -> bool {
+        let us = self.turn;
+        let (from, to) = (mv.starting_square, mv.target_square);
+
+        let piece = match self.squares[from as usize] {
+            Some((color, piece)) if color == us => piece,
+            _ => return false,
+        };
 
-        for slider in sliders {
-            let mut position = start_position;
-            let mut hits: i32 = 0;
+        if let MoveFlag::Castle(_) = mv.flag {
+            // In Chess960 the king may stay put or land on its own rook.
+        } else if from == to || self.pieces(us) & to.bitboard() != BitBoard::EMPTY {
+            return false;
+        }
 
-            for _ in 1..8 {
-                position = utils::safe_shr(
-                    utils::safe_shl(position, slider.left as _),
-                    slider.right as _,
-                );
+        let target = self.squares[to as usize];
+        let occupancy = self.blockers();
 
-                if position & relevant_blockers.0 != 0 {
-                    if hits == 0 {
-                        out |= BitBoard(position);
+        match mv.flag {
+            MoveFlag::Castle(method) => {
+                piece == Piece::King
+                    && to == Self::castling_squares(us, method).0
+                    && self.castling_allowed(us, method)
+            }
+            MoveFlag::EnPassant(other_pawn) => {
+                piece == Piece::Pawn
+                    && to.bitboard() & self.en_passant != BitBoard::EMPTY
+                    && target.is_none()
+                    && other_pawn == Square::new(to.file(), from.rank())
+                    && self.squares[other_pawn as usize] == Some((us.opponent(), Piece::Pawn))
+                    && movegen::pawn_attacks(from, us) & to.bitboard() != BitBoard::EMPTY
+            }
+            MoveFlag::Promotion(promotion) => {
+                piece == Piece::Pawn
+                    && Piece::PROMOTIONS.contains(&promotion)
+                    && to.rank() == us.promotion_rank()
+                    && match target {
+                        None => self.pawn_pushes(from, us) & to.bitboard() != BitBoard::EMPTY,
+                        Some((_, Piece::King)) => false,
+                        Some(_) => {
+                            movegen::pawn_attacks(from, us) & to.bitboard() != BitBoard::EMPTY
+                        }
                     }
+            }
+            MoveFlag::Capture(captured) => {
+                captured != Piece::King
+                    && target == Some((us.opponent(), captured))
+                    && !(piece == Piece::Pawn && to.rank() == us.promotion_rank())
+                    && movegen::piece_attacks(piece, us, from, occupancy) & to.bitboard()
+                        != BitBoard::EMPTY
+            }
+            MoveFlag::None => {
+                target.is_none()
+                    && match piece {
+                        Piece::Pawn => {
+                            to.rank() != us.promotion_rank()
+                                && self.pawn_pushes(from, us) & to.bitboard() != BitBoard::EMPTY
+                        }
+                        _ => {
+                            movegen::piece_attacks(piece, us, from, occupancy) & to.bitboard()
+                                != BitBoard::EMPTY
+                        }
+                    }
+            }
+            MoveFlag::NullMove | MoveFlag::Untargeted => false,
+        }
+    }
 
-                    break;
-                } else if hits == 0 {
-                    out |= BitBoard(position);
+    /// [Board::is_pseudo_legal] and doesn't leave the own king in check.
+    pub fn is_legal(&self, mv: Move) -> bool {
+        self.is_pseudo_legal(mv) && self.keeps_king_safe(self.turn, mv)
+    }
+
+    /// Whether the pseudo-legal move `mv` of `us` leaves their king out of check.
+    fn keeps_king_safe(&self, us: Color, mv: Move) -> bool {
+        let them = us.opponent();
+        let (from, to) = (mv.starting_square, mv.target_square);
+
+        if let MoveFlag::Castle(method) = mv.flag {
+            return !self.castling_path_attacked(us, method);
+        }
+
+        let mut removed = to.bitboard();
+
+        if let MoveFlag::EnPassant(other_pawn) = mv.flag {
+            removed |= other_pawn.bitboard();
+        }
+
+        let occupancy =
+            BitBoard((self.blockers().0 & !from.bitboard().0 & !removed.0) | to.bitboard().0);
+
+        let king = match self.squares[from as usize] {
+            Some((_, Piece::King)) => to,
+            _ => self.king_square(us),
+        };
+
+        self.attackers_of(king, occupancy, them) & !removed == BitBoard::EMPTY
+    }
+
+    /// Pawn pushes, including the double push from the starting rank.
+    fn pawn_pushes(&self, square: Square, color: Color) -> BitBoard {
+        let single = square.bitboard().forward(color) & !self.blockers();
+
+        let start_rank = match color {
+            Color::White => Rank::Second,
+            Color::Black => Rank::Seventh,
+        };
+
+        if square.rank() != start_rank {
+            return single;
+        }
+
+        single | (single.forward(color) & !self.blockers())
+    }
+
+    /// Legal moves of `color`, which is expected to be the side to move.
+    pub fn generate_moves(&self, color: Color) -> Vec<Move> {
+        let mut out = Vec::with_capacity(64);
+
+        let own = self.pieces(color);
+        let capturable =
+            self.pieces(color.opponent()) & !self.bitboards.get_piece_set(Piece::King, None);
+        let occupancy = self.blockers();
+
+        for from in own.active_squares() {
+            let Some((_, piece)) = self.squares[from as usize] else {
+                continue;
+            };
+
+            let targets = match piece {
+                Piece::Pawn => {
+                    self.pawn_pushes(from, color)
+                        | (movegen::pawn_attacks(from, color) & capturable)
+                }
+                _ => {
+                    movegen::piece_attacks(piece, color, from, occupancy)
+                        & !own
+                        & (capturable | !occupancy)
                 }
+            };
 
-                /* if position & BitBoard::CORNERS.0 != 0 {
-                    break;
-                }*/
+            for to in targets.active_squares() {
+                if piece == Piece::Pawn && to.rank() == color.promotion_rank() {
+                    for promotion in Piece::PROMOTIONS {
+                        out.push(Move {
+                            starting_square: from,
+                            target_square: to,
+                            flag: MoveFlag::Promotion(promotion),
+                        });
+                    }
+
+                    continue;
+                }
+
+                out.push(Move {
+                    starting_square: from,
+                    target_square: to,
+                    flag: match self.squares[to as usize] {
+                        Some((_, captured)) => MoveFlag::Capture(captured),
+                        None => MoveFlag::None,
+                    },
+                });
+            }
+
+            if piece == Piece::Pawn && color == self.turn {
+                for to in (movegen::pawn_attacks(from, color) & self.en_passant).active_squares() {
+                    out.push(Move {
+                        starting_square: from,
+                        target_square: to,
+                        flag: MoveFlag::EnPassant(Square::new(to.file(), from.rank())),
+                    });
+                }
+            }
+        }
+
+        out.retain(|mv| self.keeps_king_safe(color, *mv));
+
+        for method in [CastlingMethod::Short, CastlingMethod::Long] {
+            if self.can_castle(color, method) {
+                out.push(Move {
+                    starting_square: self.king_square(color),
+                    target_square: Self::castling_squares(color, method).0,
+                    flag: MoveFlag::Castle(method),
+                });
             }
         }
 
-        out & !self.pieces(color)
+        out
+    }
+
+    pub fn do_str_moves(&mut self, moves: &str) {
+        for mv in moves.split_whitespace() {
+            let start = Square::from_str(&mv[0..=1]).unwrap();
+            let target = Square::from_str(&mv[2..=3]).unwrap();
+        }
+    }
+
+    pub fn isolate_pieces(board: BitBoard) -> Vec<BitBoard> {
+        let mut list = vec![];
+        let mut board = board;
+        while board.0 != 0 {
+            let e = BitBoard(board.0 & board.0.wrapping_neg());
+            list.push(e);
+            board.0 ^= e.0;
+        }
+
+        list
+    }
+
+    pub fn blockers(&self) -> BitBoard {
+        self.bitboards.all_pieces(None)
     }
 
     pub fn king_square(&self, color: Color) -> Square {
@@ -815,275 +1181,963 @@ impl Board {
         Square::index(board.trailing_zeros() as usize)
     }
 
-    pub fn move_filter(&self, color: Color) -> BitBoard {
-        match color {
-            Color::White => self.move_filters[0],
-            Color::Black => self.move_filters[1],
+    pub fn can_castle_short(&self, color: Color) -> bool {
+        self.can_castle(color, CastlingMethod::Short)
+    }
+
+    pub fn can_castle_long(&self, color: Color) -> bool {
+        self.can_castle(color, CastlingMethod::Long)
+    }
+
+    /// Castling rights, king and rook placement and empty squares in between,
+    /// but not whether the king passes through check.
+    fn castling_allowed(&self, color: Color, method: CastlingMethod) -> bool {
+        let Some(rook_start) = self.castling_rook(color, method) else {
+            return false;
+        };
+
+        let king_start = self.king_square(color);
+        let (king_target, rook_target) = Self::castling_squares(color, method);
+
+        // Everything the king and rook pass or land on, apart from themselves.
+        let path = (Self::rank_span(king_start, king_target)
+            | Self::rank_span(rook_start, rook_target))
+            & !king_start.bitboard()
+            & !rook_start.bitboard();
+
+        king_start.rank() == rook_start.rank()
+            && self.squares[rook_start as usize] == Some((color, Piece::Rook))
+            && self.blockers() & path == BitBoard::EMPTY
+    }
+
+    /// Squares from `a` to `b` on their (shared) rank, both included.
+    fn rank_span(a: Square, b: Square) -> BitBoard {
+        let (low, high) = match a.file() < b.file() {
+            true => (a.file() as usize, b.file() as usize),
+            false => (b.file() as usize, a.file() as usize),
+        };
+
+        (low..=high).fold(BitBoard::EMPTY, |span, file| {
+            span | Square::new(File::index(file), a.rank()).bitboard()
+        })
+    }
+
+    /// Whether the king would castle out of, through or into check. The target
+    /// square is checked with the rook already moved, as in Chess960 the rook
+    /// can shield it before castling. The start square is always checked on
+    /// its own, since a king already on its target square could otherwise
+    /// hide a check behind the arriving rook.
+    fn castling_path_attacked(&self, color: Color, method: CastlingMethod) -> bool {
+        let king_start = self.king_square(color);
+        let rook_start = self.castling_rooks[color as usize][method as usize];
+        let (king_target, rook_target) = Self::castling_squares(color, method);
+
+        let passed = (Self::rank_span(king_start, king_target) & !king_target.bitboard())
+            | king_start.bitboard();
+
+        let after = (self.blockers() & !king_start.bitboard() & !rook_start.bitboard())
+            | king_target.bitboard()
+            | rook_target.bitboard();
+
+        passed.active_squares().into_iter().any(|square| {
+            self.attackers_of(square, self.blockers(), color.opponent()) != BitBoard::EMPTY
+        }) || self.attackers_of(king_target, after, color.opponent()) != BitBoard::EMPTY
+    }
+
+    fn can_castle(&self, color: Color, method: CastlingMethod) -> bool {
+        self.castling_allowed(color, method) && !self.castling_path_attacked(color, method)
+    }
+
+    /// King moves that don't step into an attack. The king itself doesn't block
+    /// sliders, so it can't retreat along a checking ray.
+    pub fn king_moves(&self, color: Color) -> BitBoard {
+        let square = self.king_square(color);
+        let occupancy = self.blockers() & !square.bitboard();
+
+        let mut moves = BitBoard::EMPTY;
+
+        for target in Piece::King.possible_moves(square).active_squares() {
+            if self.attackers_of(target, occupancy, color.opponent()) == BitBoard::EMPTY {
+                moves |= target.bitboard();
+            }
         }
+
+        moves
     }
 
-    // (enemy pieces that pin a piece, pinned pieces, checkers)
-    pub fn pinned_pieces(&mut self, color: Color) -> (HashMap<Square, BitBoard>, (u32, BitBoard)) {
-        let mut out = (0, BitBoard::EMPTY);
-        let mut pinned_pieces = HashMap::new();
+    pub fn in_check(&self, color: Color) -> bool {
+        self.attackers_of(self.king_square(color), self.blockers(), color.opponent())
+            != BitBoard::EMPTY
+    }
 
-        let mut position;
+    /// All squares attacked by `color`, computed from the current position.
+    pub fn attacked_squares(&self, color: Color) -> BitBoard {
+        let occupancy = self.blockers();
+        let mut attacked = BitBoard::EMPTY;
 
-        let checkers = (KNIGHT_MOVES[self.king_square(color) as usize]
-            & self
+        for piece in Piece::ALL {
+            for square in self
                 .bitboards
-                .get_piece_set(Piece::Knight, Some(color.opponent())))
-            | ((self.king_square(color).bitboard().forward(color).shr(1)
-                | self.king_square(color).bitboard().forward(color).shl(1))
-                & self
-                    .bitboards
-                    .get_piece_set(Piece::Pawn, Some(color.opponent())));
+                .get_piece_set(piece, Some(color))
+                .active_squares()
+            {
+                attacked |= movegen::piece_attacks(piece, color, square, occupancy);
+            }
+        }
 
-        out.0 += checkers.0.count_ones();
-        out.1 |= checkers;
+        attacked
+    }
 
-        for slider in Piece::Queen.sliders().unwrap() {
-            position = self.king_square(color).bitboard();
+    /// Parses a FEN string. The clock fields may be left out and default to `0 1`.
+    ///
+    /// Besides the syntax this checks that the position makes sense: one king per
+    /// side, no pawns on the back ranks, castling rights that match the king and
+    /// rook placement and an en passant square behind a pawn that just moved.
+    pub fn load_fen(fen: String) -> Result<Self, FenError> {
+        let mut result = Self::new();
 
-            //let v = Piece::Queen.possible_moves(Square::index(position.0.trailing_zeros() as _)) & self.bitboards.sliding_pieces(color.opponent());
+        // Fields with their byte offset, for error positions.
+        let mut fields = fen
+            .split(' ')
+            .scan(0, |offset, field| {
+                let start = *offset;
+                *offset += field.len() + 1;
+                Some((start, field))
+            })
+            .filter(|(_, field)| !field.is_empty());
+
+        let (_, placement) = fields
+            .next()
+            .ok_or(FenError::MissingField(0, "placement"))?;
+
+        Self::load_fen_placement(&mut result, placement)?;
+
+        let end = fen.len();
+
+        result.turn = match fields.next() {
+            Some((_, "w")) => Color::White,
+            Some((_, "b")) => Color::Black,
+            Some((position, side)) => {
+                return Err(FenError::InvalidSideToMove(position, side.to_owned()))
+            }
+            None => return Err(FenError::MissingField(end, "side to move")),
+        };
 
-            //print_bitboard(v);
+        let (position, castling) = fields
+            .next()
+            .ok_or(FenError::MissingField(end, "castling"))?;
 
-            let mut pinned = None;
+        if castling != "-" {
+            for (offset, c) in castling.char_indices() {
+                Self::load_fen_castling(&mut result, position + offset, c)?;
+            }
+        }
 
-            let mut ray = position;
+        let (position, en_passant) = fields
+            .next()
+            .ok_or(FenError::MissingField(end, "en passant"))?;
 
-            let cannot_pin = match *slider {
-                Slider::DOWN | Slider::LEFT | Slider::RIGHT | Slider::UP => {
-                    self.bitboards.get_piece_set(Piece::Bishop, None)
-                }
-                Slider::LEFTDOWN | Slider::LEFTUP | Slider::RIGHTDOWN | Slider::RIGHTUP => {
-                    self.bitboards.get_piece_set(Piece::Rook, None)
-                }
-                s => unreachable!("Unhandled Slider: {:?}", s),
+        if en_passant != "-" {
+            let square = Square::from_str(en_passant)
+                .map_err(|_| FenError::InvalidEnPassant(position, en_passant.to_owned()))?;
+
+            // The pawn that just moved stands in front of the square, the squares it passed are empty.
+            let (rank, forward) = match result.turn {
+                Color::White => (Rank::Sixth, 1),
+                Color::Black => (Rank::Third, -1),
             };
 
-            for _index in 1..8 {
-                position = BitBoard(utils::safe_shr(
-                    utils::safe_shl(position.0, slider.left as _),
-                    slider.right as _,
-                ));
+            let pawn = square.try_offset(0, -forward);
+            let start = square.try_offset(0, forward);
 
-                ray |= position;
+            if square.rank() != rank
+                || result.squares[square as usize].is_some()
+                || start.map(|sq| result.squares[sq as usize].is_some()) != Some(false)
+                || pawn.map(|sq| result.squares[sq as usize])
+                    != Some(Some((result.turn.opponent(), Piece::Pawn)))
+            {
+                return Err(FenError::InconsistentEnPassant(position, square));
+            }
 
-                if position & self.pieces(color) != BitBoard::EMPTY {
-                    if pinned.is_some() {
-                        break;
-                    }
+            result.en_passant = square.bitboard();
+        }
+
+        if let Some((position, halfmove)) = fields.next() {
+            result.halfmove_count = halfmove
+                .parse()
+                .map_err(|_| FenError::InvalidClock(position, halfmove.to_owned()))?;
+        }
 
-                    pinned = Some(Square::index(position.0.trailing_zeros() as _));
+        if let Some((position, fullmove)) = fields.next() {
+            result.move_count = match fullmove.parse() {
+                Ok(0) | Err(_) => {
+                    return Err(FenError::InvalidClock(position, fullmove.to_owned()))
                 }
+                Ok(n) => n,
+            };
+        }
 
-                let enemy_piece = position & (self.bitboards.sliding_pieces(color.opponent()));
+        if let Some((position, _)) = fields.next() {
+            return Err(FenError::TooManyFields(position));
+        }
 
-                if enemy_piece != BitBoard::EMPTY {
-                    if enemy_piece & cannot_pin != BitBoard::EMPTY {
-                        break;  
-                    }
+        Ok(result)
+    }
 
-                    // print_bitboard(enemy_piece);
+    /// One castling right, as `KQkq` (X-FEN) or as the rook's file (Shredder-FEN).
+    fn load_fen_castling(result: &mut Self, position: usize, c: char) -> Result<(), FenError> {
+        let color = match c.is_ascii_uppercase() {
+            true => Color::White,
+            false => Color::Black,
+        };
 
-                    // In Check
-                    if pinned.is_none() {
-                        out.1 |= ray;
-                        out.0 += 1;
+        let back_rank = match color {
+            Color::White => Rank::First,
+            Color::Black => Rank::Eighth,
+        };
 
-                        break;
-                    }
+        let king = result.king_square(color);
+        let is_rook = |file: usize| {
+            result.squares[Square::new(File::index(file), back_rank) as usize]
+                == Some((color, Piece::Rook))
+        };
+
+        let king_file = king.file() as usize;
+
+        let rook_file = match c.to_ascii_uppercase() {
+            'K' => (king_file + 1..8).rev().find(|file| is_rook(*file)),
+            'Q' => (0..king_file).find(|file| is_rook(*file)),
+            'A'..='H' => {
+                Some((c.to_ascii_uppercase() as u8 - b'A') as usize).filter(|file| is_rook(*file))
+            }
+            _ => return Err(FenError::InvalidCastling(position, c)),
+        };
+
+        let rook_file = match rook_file {
+            Some(file) if king.rank() == back_rank => file,
+            _ => return Err(FenError::CastlingWithoutPieces(position, c)),
+        };
+
+        let method = match rook_file > king_file {
+            true => CastlingMethod::Short,
+            false => CastlingMethod::Long,
+        };
+
+        let rights = &mut result.castling_availability[color as usize];
+        let right = match method {
+            CastlingMethod::Short => &mut rights.0,
+            CastlingMethod::Long => &mut rights.1,
+        };
+
+        if *right {
+            return Err(FenError::InvalidCastling(position, c));
+        }
 
-                    pinned_pieces.insert(pinned.expect("unreachable"), ray);
+        *right = true;
+        result.castling_rooks[color as usize][method as usize] =
+            Square::new(File::index(rook_file), back_rank);
+
+        Ok(())
+    }
+
+    fn load_fen_placement(result: &mut Self, placement: &str) -> Result<(), FenError> {
+        let mut ranks = Rank::ALL.iter().rev();
+        let mut rank = *ranks.next().unwrap();
+        let mut file = 0;
+        let mut last_was_digit = false;
+
+        for (position, p) in placement.char_indices() {
+            if p == '/' {
+                if file != 8 {
+                    return Err(FenError::RankLength(position, rank));
                 }
 
-                if position & self.pieces(color.opponent()) != BitBoard::EMPTY {
-                    break;
+                rank = *ranks.next().ok_or(FenError::RankCount(position))?;
+                file = 0;
+                last_was_digit = false;
+
+                continue;
+            }
+
+            if let Some(skip) = p.to_digit(10) {
+                if skip == 0 || skip > 8 || last_was_digit {
+                    return Err(FenError::InvalidPiece(position, p));
                 }
 
-                if position & BitBoard::CORNERS != BitBoard::EMPTY {
-                    break;
+                file += skip as usize;
+                last_was_digit = true;
+
+                if file > 8 {
+                    return Err(FenError::RankLength(position, rank));
                 }
+
+                continue;
+            }
+
+            last_was_digit = false;
+
+            let color = match p.is_uppercase() {
+                true => Color::White,
+                false => Color::Black,
+            };
+
+            let piece = Piece::from_notation(p.to_ascii_uppercase())
+                .ok_or(FenError::InvalidPiece(position, p))?;
+
+            if file >= 8 {
+                return Err(FenError::RankLength(position, rank));
             }
+
+            let square = Square::new(File::index(file), rank);
+
+            if piece == Piece::Pawn && (rank == Rank::First || rank == Rank::Eighth) {
+                return Err(FenError::PawnOnBackRank(position, square));
+            }
+
+            result.squares[square as usize] = Some((color, piece));
+            result.bitboards.insert_piece(square, piece, color);
+
+            file += 1;
+        }
+
+        if file != 8 {
+            return Err(FenError::RankLength(placement.len(), rank));
         }
 
-        (pinned_pieces, out)
+        if rank != Rank::First {
+            return Err(FenError::RankCount(placement.len()));
+        }
+
+        for color in [Color::White, Color::Black] {
+            let kings = result
+                .bitboards
+                .get_piece_set(Piece::King, Some(color))
+                .0
+                .count_ones();
+
+            if kings != 1 {
+                return Err(FenError::KingCount(0, color, kings));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod legality_tests {
+    use super::*;
+
+    fn board(fen: &str) -> Board {
+        Board::load_fen(fen.to_owned()).expect("invalid test FEN")
+    }
+
+    fn mv(starting_square: Square, target_square: Square, flag: MoveFlag) -> Move {
+        Move {
+            starting_square,
+            target_square,
+            flag,
+        }
+    }
+
+    #[test]
+    pub fn generated_moves_are_legal() {
+        let board = board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        for m in board.generate_moves(board.turn) {
+            assert!(board.is_legal(m), "{} should be legal", m);
+        }
+    }
+
+    #[test]
+    pub fn stale_moves() {
+        let board = board("4k3/8/8/8/8/8/8/4K2R b K - 0 1");
+
+        // Not our piece, nothing on the square, wrong capture target.
+        assert!(!board.is_pseudo_legal(mv(Square::H1, Square::H5, MoveFlag::None)));
+        assert!(!board.is_pseudo_legal(mv(Square::E2, Square::E4, MoveFlag::None)));
+        assert!(!board.is_pseudo_legal(mv(Square::E8, Square::E7, MoveFlag::Capture(Piece::Pawn))));
+        assert!(!board.is_pseudo_legal(mv(Square::E8, Square::E6, MoveFlag::None)));
+        assert!(board.is_pseudo_legal(mv(Square::E8, Square::E7, MoveFlag::None)));
+    }
+
+    #[test]
+    pub fn pinned_piece() {
+        let board = board("4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1");
+        let m = mv(Square::E2, Square::D3, MoveFlag::None);
+
+        assert!(board.is_pseudo_legal(m));
+        assert!(!board.is_legal(m));
+        assert!(board.is_legal(mv(Square::E1, Square::D1, MoveFlag::None)));
+    }
+
+    #[test]
+    pub fn castling_through_check() {
+        let board = board("4k3/8/8/8/8/8/5r2/R3K2R w KQ - 0 1");
+
+        assert!(!board.is_legal(mv(
+            Square::E1,
+            Square::G1,
+            MoveFlag::Castle(CastlingMethod::Short)
+        )));
+        assert!(board.is_legal(mv(
+            Square::E1,
+            Square::C1,
+            MoveFlag::Castle(CastlingMethod::Long)
+        )));
     }
 
-    const CASTLING_SQUARES: [[(Square, Square, u64); 2]; 2] = [
-        [
+    #[test]
+    pub fn en_passant_discovered_check() {
+        let board = board("8/8/8/K2pP2r/8/8/8/4k3 w - d6 0 1");
+        let m = mv(Square::E5, Square::D6, MoveFlag::EnPassant(Square::D5));
+
+        assert!(board.is_pseudo_legal(m));
+        assert!(!board.is_legal(m));
+    }
+
+    #[test]
+    pub fn capture_promotion() {
+        let mut board = board("rn2k3/1P6/8/8/8/8/8/4K3 w - - 0 1");
+        let m = mv(Square::B7, Square::A8, MoveFlag::Promotion(Piece::Queen));
+
+        assert!(board.is_legal(m));
+
+        // Pushes need an empty square, captures an enemy piece.
+        for target in [Square::B8, Square::C8] {
+            let push = mv(Square::B7, target, MoveFlag::Promotion(Piece::Queen));
+            assert!(!board.is_pseudo_legal(push));
+        }
+
+        let before = board.clone();
+        board.do_move(m).unwrap();
+
+        assert_eq!(
+            board.squares[Square::A8 as usize],
+            Some((Color::White, Piece::Queen))
+        );
+        assert_eq!(
+            board.bitboards.get_piece_set(Piece::Rook, Some(Color::Black)),
+            BitBoard::EMPTY
+        );
+
+        board.undo_move(m).unwrap();
+
+        assert_eq!(board.squares, before.squares);
+        assert!(board.bitboards == before.bitboards);
+    }
+
+    #[test]
+    pub fn attackers_both_colors() {
+        let board = board("4k3/8/3n4/8/4P3/8/8/R3K3 w - - 0 1");
+
+        assert_eq!(
+            board.attackers_to(Square::D5, board.blockers()),
+            Square::E4.bitboard()
+        );
+        assert_eq!(
+            board.attackers_to(Square::E4, board.blockers()),
+            Square::D6.bitboard()
+        );
+        assert_eq!(
+            board
+                .attackers_to(Square::E2, board.blockers())
+                .active_squares(),
+            vec![Square::E1]
+        );
+        // X-ray through the king.
+        assert_eq!(
+            board.attackers_to(
+                Square::F1,
+                BitBoard(board.blockers().0 & !Square::E1.bitboard().0)
+            ),
+            Square::E1.bitboard() | Square::A1.bitboard()
+        );
+    }
+
+    #[test]
+    pub fn checks() {
+        let cases = [
+            // Direct.
+            (
+                "4k3/8/8/8/8/8/8/R3K3 w - - 0 1",
+                mv(Square::A1, Square::A8, MoveFlag::None),
+                true,
+            ),
+            (
+                "4k3/8/8/8/8/8/8/R3K3 w - - 0 1",
+                mv(Square::A1, Square::A7, MoveFlag::None),
+                false,
+            ),
+            // Discovered by a bishop.
+            (
+                "5k2/8/8/8/1N6/B7/8/4K3 w - - 0 1",
+                mv(Square::B4, Square::D3, MoveFlag::None),
+                true,
+            ),
+            (
+                "4k3/8/8/8/1N6/B7/8/4K3 w - - 0 1",
+                mv(Square::B4, Square::D3, MoveFlag::None),
+                false,
+            ),
+            // Castling rook.
+            (
+                "5k2/8/8/8/8/8/8/4K2R w K - 0 1",
+                mv(
+                    Square::E1,
+                    Square::G1,
+                    MoveFlag::Castle(CastlingMethod::Short),
+                ),
+                true,
+            ),
             (
-                Square::E1,
-                Square::H1,
-                Square::F1.bitboard().0 | Square::G1.bitboard().0,
+                "3k4/8/8/8/8/8/8/R3K3 w Q - 0 1",
+                mv(
+                    Square::E1,
+                    Square::C1,
+                    MoveFlag::Castle(CastlingMethod::Long),
+                ),
+                true,
             ),
+            // Promotion.
             (
-                Square::E1,
-                Square::A1,
-                Square::B1.bitboard().0 | Square::C1.bitboard().0 | Square::D1.bitboard().0,
+                "1k6/4P3/8/8/8/8/8/4K3 w - - 0 1",
+                mv(Square::E7, Square::E8, MoveFlag::Promotion(Piece::Queen)),
+                true,
             ),
-        ],
-        [
             (
-                Square::E8,
-                Square::H8,
-                Square::F8.bitboard().0 | Square::G8.bitboard().0,
+                "1k6/4P3/8/8/8/8/8/4K3 w - - 0 1",
+                mv(Square::E7, Square::E8, MoveFlag::Promotion(Piece::Bishop)),
+                false,
             ),
+            // En passant opening the rank.
             (
-                Square::E8,
-                Square::A8,
-                Square::B8.bitboard().0 | Square::C8.bitboard().0 | Square::D8.bitboard().0,
+                "8/8/8/R2pP2k/8/8/8/4K3 w - d6 0 1",
+                mv(Square::E5, Square::D6, MoveFlag::EnPassant(Square::D5)),
+                true,
             ),
-        ],
-    ];
+        ];
 
-    pub fn can_castle_short(&self, color: Color) -> bool {
-        self.castling_availability[color as usize].0
-            && !self.in_check(color)
-            // Double check if the placement is really correct.
-            && self.king_square(color) == Self::CASTLING_SQUARES[color as usize][0].0
-            && (self.bitboards.get_piece_set(Piece::Rook, Some(color)) & Self::CASTLING_SQUARES[color as usize][0].1.bitboard()
-                != BitBoard::EMPTY)
-            
-            // Free of pieces & attacked squares?
-            && (self.bitboards.all_pieces(Some(color))
-                & BitBoard(Self::CASTLING_SQUARES[color as usize][0].2)
-                == BitBoard::EMPTY)
-            && (self.bitboards.0[BitBoards::ad_bitboard(color.opponent())]
-                & BitBoard(Self::CASTLING_SQUARES[color as usize][0].2)
-                == BitBoard::EMPTY)
+        for (fen, m, expected) in cases {
+            assert_eq!(board(fen).gives_check(m), expected, "{} in {}", m, fen);
+        }
     }
 
-    pub fn can_castle_long(&self, color: Color) -> bool {
-        self.castling_availability[color as usize].0
-            && !self.in_check(color)
-
-            // Double check if the placement is really correct.
-            && self.king_square(color) == Self::CASTLING_SQUARES[color as usize][1].0
-            && (self.bitboards.get_piece_set(Piece::Rook, Some(color)) & Self::CASTLING_SQUARES[color as usize][1].1.bitboard()
-                != BitBoard::EMPTY)
-            
-            // Free of pieces & attacked squares?
-            && (self.bitboards.all_pieces(Some(color))
-                & BitBoard(Self::CASTLING_SQUARES[color as usize][1].2)
-                == BitBoard::EMPTY)
-            && (self.bitboards.0[BitBoards::ad_bitboard(color.opponent())]
-                & BitBoard(Self::CASTLING_SQUARES[color as usize][1].2)
-                == BitBoard::EMPTY)
+    #[test]
+    pub fn in_check_without_generation() {
+        let mut board = board("4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+
+        assert!(!board.in_check(Color::Black));
+
+        board
+            .do_move(mv(Square::A1, Square::A8, MoveFlag::None))
+            .unwrap();
+
+        assert!(board.in_check(Color::Black));
+        assert!(!board.in_check(Color::White));
+        assert_eq!(
+            board.king_moves(Color::Black).active_squares(),
+            vec![Square::D7, Square::E7, Square::F7]
+        );
+
+        board
+            .undo_move(mv(Square::A1, Square::A8, MoveFlag::None))
+            .unwrap();
+
+        assert!(!board.in_check(Color::Black));
     }
 
-    pub fn king_moves(&self, color: Color) -> BitBoard {
-        let board = self.bitboards.get_piece_set(Piece::King, Some(color)).0;
+    #[test]
+    pub fn castling_rights_on_demand() {
+        let board = board("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+
+        assert!(board.can_castle_short(Color::White));
+        assert!(board.can_castle_long(Color::White));
+        assert!(board.can_castle_short(Color::Black));
+        assert!(board.can_castle_long(Color::Black));
+
+        // Only b1 is attacked, the king doesn't pass it.
+        let board = self::board("r3k2r/8/8/8/8/n7/8/R3K2R w KQkq - 0 1");
+
+        assert!(board.can_castle_short(Color::White));
+        assert!(board.can_castle_long(Color::White));
 
-        assert_eq!(board.count_ones(), 1);
+        let board = self::board("r3k2r/8/8/8/8/4n3/8/R3K2R w KQkq - 0 1");
 
-        let square = Square::index(board.trailing_zeros() as usize);
+        assert!(!board.can_castle_short(Color::White));
+        assert!(!board.can_castle_long(Color::White));
 
-        Piece::King.possible_moves(square)
-            & !self.bitboards.0[BitBoards::ad_bitboard(color.opponent())]
+        let board = self::board("r3k2r/8/8/8/8/8/8/R3K2R w Kk - 0 1");
+
+        assert!(!board.can_castle_long(Color::White));
+        assert!(!board.can_castle_long(Color::Black));
     }
 
-    pub fn in_check(&self, color: Color) -> bool {
-        self.bitboards.get_piece_set(Piece::King, Some(color))
-            & self.bitboards.0[BitBoards::ad_bitboard(color.opponent())]
-            != BitBoard::EMPTY
+    #[test]
+    pub fn do_move_empty_square() {
+        let mut board = board("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+
+        assert_eq!(
+            board.do_move(mv(Square::E2, Square::E4, MoveFlag::None)),
+            Err(MoveError::EmptySquare(Square::E2))
+        );
     }
+}
 
-    pub fn load_fen(fen: String) -> Option<Self> {
-        let mut result = Self::new();
+#[cfg(test)]
+mod fen_tests {
+    use super::*;
 
-        let mut rank = 8u8;
-        let mut file = 1u8;
+    const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
-        let mut parts = fen.split(' ');
+    fn play(board: &mut Board, moves: &str) {
+        for mv in moves.split_whitespace() {
+            let mv = board
+                .uci_to_board_move(board.turn, UciMove::parse(mv).unwrap())
+                .unwrap();
 
-        let placement = parts.next()?;
+            assert!(board.is_legal(mv), "{} is illegal in {}", mv, board);
+            board.do_move(mv).unwrap();
+        }
+    }
 
-        for p in placement.chars() {
-            if rank == 0 {
-                break;
-            }
+    #[test]
+    pub fn startpos() {
+        let board = Board::load_fen(STARTPOS.to_owned()).unwrap();
 
-            if let Some(skip) = p.to_digit(10) {
-                assert!(skip <= 8);
-                assert!(skip != 0);
+        assert_eq!(board.to_fen(), STARTPOS);
+        assert_eq!(board.to_string(), STARTPOS);
+    }
 
-                file = (file % 8) + (skip as u8);
+    #[test]
+    pub fn clocks_and_en_passant() {
+        let mut board = Board::load_fen(STARTPOS.to_owned()).unwrap();
+
+        play(&mut board, "e2e4");
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1"
+        );
+        assert_eq!(
+            board.to_fen_with(FenEnPassant::Always),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+
+        play(&mut board, "d7d5 e4e5 f7f5");
+        assert_eq!(
+            board.to_fen(),
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3"
+        );
+
+        play(&mut board, "g1f3 g8f6 f1e2 b8c6 e1g1");
+        assert_eq!(
+            board.to_fen(),
+            "r1bqkb1r/ppp1p1pp/2n2n2/3pPp2/8/5N2/PPPPBPPP/RNBQ1RK1 b kq - 5 5"
+        );
+    }
 
-                continue;
-            }
+    #[test]
+    pub fn castling_rights() {
+        let mut board = Board::load_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1".to_owned()).unwrap();
 
-            if p == '/' {
-                rank -= 1;
-                file = 1;
+        play(&mut board, "a1a8");
+        assert_eq!(board.to_fen(), "R3k2r/8/8/8/8/8/8/4K2R b Kk - 0 1");
 
-                continue;
-            }
+        play(&mut board, "e8e7");
+        assert_eq!(board.to_fen(), "R6r/4k3/8/8/8/8/8/4K2R w K - 1 2");
+    }
 
-            let color = match p.is_uppercase() {
-                true => Color::White,
-                false => Color::Black,
-            };
+    #[test]
+    pub fn chess960_castling_fields() {
+        let shredder =
+            Board::load_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w HAha - 0 1".to_owned())
+                .unwrap();
+        assert_eq!(shredder, Board::load_fen(STARTPOS.to_owned()).unwrap());
+        assert_eq!(shredder.to_fen(), STARTPOS);
+
+        let board = Board::load_fen("2r1kr2/8/8/8/8/8/8/1R2K1R1 w GBfc - 0 1".to_owned()).unwrap();
+        assert_eq!(
+            board.castling_rights()[0],
+            [Some(Square::G1), Some(Square::B1)]
+        );
+        assert_eq!(
+            board.castling_rights()[1],
+            [Some(Square::F8), Some(Square::C8)]
+        );
+        assert_eq!(board.to_fen(), "2r1kr2/8/8/8/8/8/8/1R2K1R1 w KQkq - 0 1");
+
+        // Inner rooks need their file to tell them apart from the outer ones.
+        let board = Board::load_fen("4k1rr/8/8/8/8/8/8/4K1RR w Gg - 0 1".to_owned()).unwrap();
+        assert_eq!(
+            board.castling_rook(Color::White, CastlingMethod::Short),
+            Some(Square::G1)
+        );
+        assert_eq!(board.to_fen(), "4k1rr/8/8/8/8/8/8/4K1RR w Gg - 0 1");
+
+        let board = Board::load_fen("4k1rr/8/8/8/8/8/8/4K1RR w K - 0 1".to_owned()).unwrap();
+        assert_eq!(
+            board.castling_rook(Color::White, CastlingMethod::Short),
+            Some(Square::H1)
+        );
+
+        assert_eq!(
+            Board::load_fen("4k3/8/8/8/8/8/8/4K1RR w C - 0 1".to_owned()),
+            Err(FenError::CastlingWithoutPieces(24, 'C'))
+        );
+        assert_eq!(
+            Board::load_fen("4k3/8/8/8/8/8/8/4K1RR w GH - 0 1".to_owned()),
+            Err(FenError::InvalidCastling(25, 'H'))
+        );
+    }
 
-            let piece = match p.to_ascii_lowercase() {
-                'p' => Piece::Pawn,
-                'n' => Piece::Knight,
-                'b' => Piece::Bishop,
-                'r' => Piece::Rook,
-                'q' => Piece::Queen,
-                'k' => Piece::King,
-                _ => panic!("Invalid FEN"),
-            };
+    #[test]
+    pub fn mirrored() {
+        let board =
+            Board::load_fen("r3k2r/pp1n1ppp/8/2pP4/8/8/PPP2PPP/R3K1R1 w Qkq c6 4 12".to_owned())
+                .unwrap();
+        let mirrored = board.mirrored();
+
+        assert_eq!(
+            mirrored.to_fen(),
+            "r3k1r1/ppp2ppp/8/8/2Pp4/8/PP1N1PPP/R3K2R b KQq c3 4 12"
+        );
+        assert_eq!(mirrored.mirrored(), board);
+        assert_eq!(
+            mirrored.generate_moves(mirrored.turn).len(),
+            board.generate_moves(board.turn).len()
+        );
+
+        let board = Board::load_fen("4k1rr/8/8/8/8/8/8/4K1RR w Gg - 0 1".to_owned()).unwrap();
+        assert_eq!(
+            board.mirrored().to_fen(),
+            "4k1rr/8/8/8/8/8/8/4K1RR b Gg - 0 1"
+        );
+    }
 
-            result.squares[(((rank - 1) * 8) + (file - 1)) as usize] = Some((color, piece));
+    #[test]
+    pub fn ascii_and_checkers() {
+        let board = Board::load_fen("4k3/8/8/8/8/3n4/8/R3K3 w Q - 0 1".to_owned()).unwrap();
+
+        assert_eq!(
+            board.to_ascii(),
+            [
+                " +---+---+---+---+---+---+---+---+",
+                " |   |   |   |   | k |   |   |   | 8",
+                " +---+---+---+---+---+---+---+---+",
+                " |   |   |   |   |   |   |   |   | 7",
+                " +---+---+---+---+---+---+---+---+",
+                " |   |   |   |   |   |   |   |   | 6",
+                " +---+---+---+---+---+---+---+---+",
+                " |   |   |   |   |   |   |   |   | 5",
+                " +---+---+---+---+---+---+---+---+",
+                " |   |   |   |   |   |   |   |   | 4",
+                " +---+---+---+---+---+---+---+---+",
+                " |   |   |   | n |   |   |   |   | 3",
+                " +---+---+---+---+---+---+---+---+",
+                " |   |   |   |   |   |   |   |   | 2",
+                " +---+---+---+---+---+---+---+---+",
+                " | R |   |   |   | K |   |   |   | 1",
+                " +---+---+---+---+---+---+---+---+",
+                "   a   b   c   d   e   f   g   h",
+                "",
+            ]
+            .join("\n")
+        );
+
+        assert_eq!(board.checkers().active_squares(), [Square::D3]);
+        assert_eq!(board.mirrored().checkers().active_squares(), [Square::D6]);
+
+        let quiet = Board::load_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1".to_owned()).unwrap();
+        assert_eq!(quiet.checkers(), BitBoard::EMPTY);
+    }
 
-            result.bitboards.insert_piece(
-                Square::new(File::index((file - 1) as _), Rank::index((rank - 1) as _)),
-                piece,
-                color,
-            );
+    #[test]
+    pub fn chess960_castling_moves() {
+        let fen = "rk2r3/8/8/8/8/8/8/RK2R3 w KQkq - 0 1";
+        let mut board = Board::load_fen(fen.to_owned()).unwrap();
+        board.chess960 = true;
+
+        let castle = board
+            .uci_to_board_move(Color::White, UciMove::parse("b1a1").unwrap())
+            .unwrap();
+        assert_eq!(castle.flag, MoveFlag::Castle(CastlingMethod::Long));
+        assert_eq!(castle.target_square, Square::C1);
+        assert_eq!(board.board_to_uci_move(castle).to_string(), "b1a1");
+
+        // The king only steps aside, the rook lands next to it.
+        play(&mut board, "b1a1");
+        assert_eq!(board.to_fen(), "rk2r3/8/8/8/8/8/8/2KRR3 b kq - 1 1");
+
+        board.undo_move(castle).unwrap();
+        assert_eq!(board.to_fen(), fen);
+
+        board.chess960 = false;
+        assert_eq!(board.board_to_uci_move(castle).to_string(), "b1c1");
+
+        let mut board = Board::load_fen(STARTPOS.to_owned()).unwrap();
+        play(&mut board, "g1f3 g8f6 g2g3 g7g6 f1g2 f8g7 e1g1 e8h8");
+        assert_eq!(
+            board.to_fen(),
+            "rnbq1rk1/ppppppbp/5np1/8/8/5NP1/PPPPPPBP/RNBQ1RK1 w - - 4 5"
+        );
+
+        // Before castling the b1 rook shields c1 from the a1 rook, afterwards it doesn't.
+        let board = Board::load_fen("4k3/8/8/8/8/8/8/rR3K2 w Q - 0 1".to_owned()).unwrap();
+        assert!(!board.is_legal(Move {
+            starting_square: Square::F1,
+            target_square: Square::C1,
+            flag: MoveFlag::Castle(CastlingMethod::Long),
+        }));
+
+        // The king stays on g1, so the rook arriving on f1 would block the check.
+        let board = Board::load_fen("4k3/8/8/8/8/8/8/4q1KR w H - 0 1".to_owned()).unwrap();
+        assert!(!board.is_legal(Move {
+            starting_square: Square::G1,
+            target_square: Square::G1,
+            flag: MoveFlag::Castle(CastlingMethod::Short),
+        }));
+    }
 
-            file += 1;
-        }
+    #[test]
+    pub fn errors() {
+        let cases = [
+            ("", FenError::MissingField(0, "placement")),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR",
+                FenError::MissingField(43, "side to move"),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNX w KQkq - 0 1",
+                FenError::InvalidPiece(42, 'X'),
+            ),
+            (
+                "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                FenError::InvalidPiece(18, '9'),
+            ),
+            (
+                "rnbqkbnr/pppppppp/44/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                FenError::InvalidPiece(19, '4'),
+            ),
+            (
+                "rnbqkbnr/ppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                FenError::RankLength(16, Rank::Seventh),
+            ),
+            (
+                "rnbqkbnr/pppppppp/7/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                FenError::RankLength(19, Rank::Sixth),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+                FenError::RankCount(41),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR/8 w KQkq - 0 1",
+                FenError::RankCount(43),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+                FenError::InvalidSideToMove(44, "x".to_owned()),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQxq - 0 1",
+                FenError::InvalidCastling(48, 'x'),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KKkq - 0 1",
+                FenError::InvalidCastling(47, 'K'),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBN1 w KQkq - 0 1",
+                FenError::CastlingWithoutPieces(46, 'K'),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e9 0 1",
+                FenError::InvalidEnPassant(51, "e9".to_owned()),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e6 0 1",
+                FenError::InconsistentEnPassant(51, Square::E6),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - x 1",
+                FenError::InvalidClock(53, "x".to_owned()),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 0",
+                FenError::InvalidClock(55, "0".to_owned()),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 x",
+                FenError::TooManyFields(57),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQQBNR w kq - 0 1",
+                FenError::KingCount(0, Color::White, 0),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBKKBNR w kq - 0 1",
+                FenError::KingCount(0, Color::White, 2),
+            ),
+            (
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNP w kq - 0 1",
+                FenError::PawnOnBackRank(42, Square::H1),
+            ),
+        ];
 
-        match parts.next() {
-            Some("w") => result.turn = Color::White,
-            Some("b") => result.turn = Color::Black,
-            _ => panic!("Invalid FEN."),
+        for (fen, error) in cases {
+            assert_eq!(Board::load_fen(fen.to_owned()), Err(error), "{}", fen);
         }
+    }
 
-        let castling = parts.next()?;
+    #[test]
+    pub fn optional_clocks() {
+        let board = Board::load_fen("4k3/8/8/8/8/8/8/4K3 b - -".to_owned()).unwrap();
 
-        if castling != "-" {
-            for c in castling.chars() {
-                match c {
-                    'K' => result.castling_availability[0].0 = true,
-                    'Q' => result.castling_availability[0].1 = true,
-                    'k' => result.castling_availability[1].0 = true,
-                    'q' => result.castling_availability[1].1 = true,
-                    _ => {}
-                }
-            }
-        }
+        assert_eq!(board.to_fen(), "4k3/8/8/8/8/8/8/4K3 b - - 0 1");
+    }
 
-        'en_passant: {
-            let name = parts.next()?;
+    /// `load_fen(to_fen(b)) == b` for every position of some pseudo-random games.
+    #[test]
+    pub fn roundtrip_random_games() {
+        let mut seed = 0x9e3779b97f4a7c15u64;
 
-            if name.contains('-') {
-                break 'en_passant;
-            }
+        for _ in 0..40 {
+            let mut board = Board::load_fen(STARTPOS.to_owned()).unwrap();
 
-            match Square::from_str(name.trim().to_ascii_uppercase().as_str()) {
-                Ok(sq) => result.en_passant = sq.bitboard(),
-                Err(e) => eprintln!("Failed to load FEN: {}, {:?}", e.to_string(), name),
-            }
-        };
+            for _ in 0..120 {
+                let fen = board.to_fen();
+                let loaded = Board::load_fen(fen.clone()).unwrap();
+
+                assert_eq!(loaded, board, "{}", fen);
+                assert_eq!(loaded.to_fen(), fen);
+
+                let moves = board
+                    .generate_moves(board.turn)
+                    .into_iter()
+                    .filter(|mv| board.is_legal(*mv))
+                    .collect::<Vec<_>>();
+
+                if moves.is_empty() {
+                    break;
+                }
+
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+
+                let mv = moves[seed as usize % moves.len()];
+                let before = board.to_fen_with(FenEnPassant::Always);
 
-        result.halfmove_count = parts.next()?.parse().ok()?;
-        result.move_count = parts.next()?.parse().ok()?;
+                board.do_move(mv).unwrap();
+                board.undo_move(mv).unwrap();
+                assert_eq!(
+                    board.to_fen_with(FenEnPassant::Always),
+                    before,
+                    "undo {}",
+                    mv
+                );
 
-        Some(result)
+                board.do_move(mv).unwrap();
+            }
+        }
     }
 }
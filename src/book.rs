@@ -0,0 +1,130 @@
+//! Opening book kept as EPD: the `bm` moves of each record are the book
+//! moves of its position, e.g. `rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR
+//! w KQkq - bm e4 d4;`. Lines starting with `#` are comments.
+
+use std::collections::HashMap;
+
+use crate::{
+    board::Board,
+    epd::{Epd, EpdError},
+    r#move::Move,
+};
+
+/// Why [Book::load] or [Book::parse] failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BookError {
+    /// The file couldn't be read.
+    Io(String),
+    /// 1-based line of an invalid record.
+    Epd(usize, EpdError),
+}
+
+impl core::fmt::Display for BookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "cannot read book: {}", e),
+            Self::Epd(line, e) => write!(f, "invalid book line {}: {}", line, e),
+        }
+    }
+}
+
+impl std::error::Error for BookError {}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Book {
+    /// Book moves by the Zobrist key of their position, in the order listed.
+    moves: HashMap<u64, Vec<Move>>,
+}
+
+impl Book {
+    pub fn load(path: &str) -> Result<Self, BookError> {
+        let text = std::fs::read_to_string(path).map_err(|e| BookError::Io(e.to_string()))?;
+
+        Self::parse(&text)
+    }
+
+    /// Records of the same position add to its moves.
+    pub fn parse(text: &str) -> Result<Self, BookError> {
+        let mut book = Self::default();
+
+        for (line, record) in text.lines().enumerate() {
+            if record.trim().is_empty() || record.starts_with('#') {
+                continue;
+            }
+
+            let epd = Epd::parse(record).map_err(|e| BookError::Epd(line + 1, e))?;
+
+            book.moves
+                .entry(epd.board.zobrist())
+                .or_default()
+                .extend(epd.best_moves());
+        }
+
+        Ok(book)
+    }
+
+    /// Number of positions in the book.
+    pub fn len(&self) -> usize {
+        self.moves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.moves.is_empty()
+    }
+
+    /// The first book move of the position that is legal in it.
+    pub fn probe(&self, board: &Board) -> Option<Move> {
+        self.moves
+            .get(&board.zobrist())?
+            .iter()
+            .copied()
+            .find(|&mv| board.is_legal(mv))
+    }
+}
+
+#[cfg(test)]
+mod book_tests {
+    use super::*;
+    use crate::uci::UciMove;
+
+    const BOOK: &str = "# White's first moves\n\
+                        rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - bm e4;\n\
+                        \n\
+                        rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - bm d4;\n\
+                        rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - bm c5;\n";
+
+    fn board(fen: &str) -> Board {
+        Board::load_fen(fen.to_owned()).unwrap()
+    }
+
+    #[test]
+    pub fn probe() {
+        let book = Book::parse(BOOK).unwrap();
+        assert_eq!(book.len(), 2);
+
+        let mut board = board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        let mv = book.probe(&board).unwrap();
+        assert_eq!(board.board_to_uci_move(mv), UciMove::parse("e2e4").unwrap());
+
+        // Clocks don't matter, the position does.
+        board.do_move(mv).unwrap();
+        board.halfmove_count = 7;
+        let mv = book.probe(&board).unwrap();
+        assert_eq!(board.board_to_uci_move(mv), UciMove::parse("c7c5").unwrap());
+
+        board.do_move(mv).unwrap();
+        assert_eq!(book.probe(&board), None);
+    }
+
+    #[test]
+    pub fn errors() {
+        assert!(matches!(
+            Book::parse("# comment\n4k3/8/8/8/8/8/8/4K3 w - - bm Qh5;"),
+            Err(BookError::Epd(2, EpdError::San(_)))
+        ));
+        assert!(matches!(
+            Book::load("/nonexistent/book.epd"),
+            Err(BookError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,312 @@
+//! Perft divide: node counts below every root move, as printed by `go perft`.
+//! Comparing them with a reference engine and following the first move whose
+//! count differs leads to the position where move generation goes wrong.
+
+use crate::{
+    board::Board,
+    perft::{self, PerftOptions},
+    uci::UciMove,
+};
+
+/// Node count below every legal move of a position.
+pub type Divide = Vec<(UciMove, u64)>;
+
+/// One way our divide disagrees with the reference's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mismatch {
+    /// Legal according to the reference, but not generated by us.
+    Missing(UciMove),
+    /// Generated by us, but not legal according to the reference.
+    Extra(UciMove),
+    /// Both agree on the moves but not on the count below this one.
+    Count { mv: UciMove, ours: u64, theirs: u64 },
+}
+
+impl core::fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing(mv) => write!(f, "missing {}", mv),
+            Self::Extra(mv) => write!(f, "extra {}", mv),
+            Self::Count { mv, ours, theirs } => {
+                write!(f, "{}: {} nodes, expected {}", mv, ours, theirs)
+            }
+        }
+    }
+}
+
+/// The deepest position where we disagree with the reference.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Moves from the starting position to the diverging one.
+    pub path: Vec<UciMove>,
+    /// FEN of the diverging position.
+    pub fen: String,
+    /// Perft depth the divides were compared at.
+    pub depth: u32,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl core::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "fen: {}", self.fen)?;
+
+        write!(f, "moves:")?;
+        for mv in &self.path {
+            write!(f, " {}", mv)?;
+        }
+        writeln!(f)?;
+
+        write!(f, "depth: {}", self.depth)?;
+        for mismatch in &self.mismatches {
+            write!(f, "\n  {}", mismatch)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Our divide of `board` at `depth`, with moves written the way a GUI would
+/// (king takes rook for castling in Chess960).
+pub fn divide(board: &Board, depth: u32) -> Divide {
+    perft::perft(board, depth, PerftOptions::default())
+        .root_nodes
+        .into_iter()
+        .map(|(mv, nodes)| (board.board_to_uci_move(mv), nodes))
+        .collect()
+}
+
+/// Reads the `e2e4: 20` lines of `go perft` output, ignoring everything else.
+pub fn parse_divide(output: &str) -> Divide {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (mv, nodes) = line.trim().split_once(':')?;
+
+            Some((UciMove::parse(mv.trim())?, nodes.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+/// Compares our divides with `reference` from `board` down, following the
+/// first move whose count differs. `reference` is asked for the divide of
+/// the position after a list of moves from `board`, at a given depth.
+///
+/// Returns `Ok(None)` if both agree at `depth`.
+pub fn find_divergence<E>(
+    board: &Board,
+    depth: u32,
+    mut reference: impl FnMut(&[UciMove], u32) -> Result<Divide, E>,
+) -> Result<Option<Divergence>, E> {
+    diverge(&mut board.clone(), &mut vec![], depth, &mut reference)
+}
+
+fn diverge<E>(
+    board: &mut Board,
+    path: &mut Vec<UciMove>,
+    depth: u32,
+    reference: &mut impl FnMut(&[UciMove], u32) -> Result<Divide, E>,
+) -> Result<Option<Divergence>, E> {
+    if depth == 0 {
+        return Ok(None);
+    }
+
+    let ours = divide(board, depth);
+    let theirs = reference(path, depth)?;
+
+    let missing = theirs
+        .iter()
+        .filter(|(mv, _)| !ours.iter().any(|(other, _)| other == mv))
+        .map(|&(mv, _)| Mismatch::Missing(mv));
+
+    let extra = ours
+        .iter()
+        .filter(|(mv, _)| !theirs.iter().any(|(other, _)| other == mv))
+        .map(|&(mv, _)| Mismatch::Extra(mv));
+
+    let mut mismatches = missing.chain(extra).collect::<Vec<_>>();
+
+    let differing = ours.iter().find_map(|&(mv, nodes)| {
+        let &(_, expected) = theirs.iter().find(|(other, _)| *other == mv)?;
+
+        (nodes != expected).then_some(Mismatch::Count {
+            mv,
+            ours: nodes,
+            theirs: expected,
+        })
+    });
+
+    // A wrong move list is the bug itself, a wrong count means it's further down.
+    if mismatches.is_empty() {
+        let Some(count @ Mismatch::Count { mv, .. }) = differing else {
+            return Ok(None);
+        };
+
+        let board_mv = board
+            .uci_to_board_move(board.turn, mv)
+            .expect("generated move can't be read back");
+
+        board.do_move(board_mv).expect("generated move failed");
+        path.push(mv);
+
+        let below = diverge(board, path, depth - 1, reference)?;
+
+        path.pop();
+        board
+            .undo_move(board_mv)
+            .expect("failed to undo generated move");
+
+        // Only the count differs if the positions below all agree.
+        match below {
+            Some(divergence) => return Ok(Some(divergence)),
+            None => mismatches.push(count),
+        }
+    }
+
+    Ok(Some(Divergence {
+        path: path.clone(),
+        fen: board.to_fen(),
+        depth,
+        mismatches,
+    }))
+}
+
+#[cfg(test)]
+mod divide_tests {
+    use super::*;
+
+    const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    fn moves(moves: &str) -> Vec<UciMove> {
+        moves
+            .split_whitespace()
+            .map(|mv| UciMove::parse(mv).unwrap())
+            .collect()
+    }
+
+    /// A reference engine that doesn't know the last move of `bug`, a move
+    /// path from `root`.
+    fn reference(root: &Board, bug: &[UciMove], path: &[UciMove], depth: u32) -> Divide {
+        fn count(board: &mut Board, line: &mut Vec<UciMove>, bug: &[UciMove], depth: u32) -> u64 {
+            if depth == 0 {
+                return 1;
+            }
+
+            let mut nodes = 0;
+
+            for mv in board.generate_moves(board.turn) {
+                line.push(board.board_to_uci_move(mv));
+
+                if line != bug {
+                    board.do_move(mv).unwrap();
+                    nodes += count(board, line, bug, depth - 1);
+                    board.undo_move(mv).unwrap();
+                }
+
+                line.pop();
+            }
+
+            nodes
+        }
+
+        let mut board = root.clone();
+
+        for mv in path {
+            let mv = board.uci_to_board_move(board.turn, *mv).unwrap();
+            board.do_move(mv).unwrap();
+        }
+
+        let mut line = path.to_vec();
+
+        board
+            .generate_moves(board.turn)
+            .into_iter()
+            .filter_map(|mv| {
+                line.push(board.board_to_uci_move(mv));
+
+                let nodes = (line != bug).then(|| {
+                    let mut board = board.clone();
+                    board.do_move(mv).unwrap();
+                    count(&mut board, &mut line, bug, depth - 1)
+                });
+
+                line.pop().zip(nodes)
+            })
+            .collect()
+    }
+
+    #[test]
+    pub fn parse() {
+        let output = "info string perft\na2a3: 380\n  e7e8q: 12 \n\nNodes searched: 392\n";
+
+        assert_eq!(
+            parse_divide(output),
+            vec![
+                (UciMove::parse("a2a3").unwrap(), 380),
+                (UciMove::parse("e7e8q").unwrap(), 12),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn agreement() {
+        let board = Board::load_fen(STARTPOS.to_owned()).unwrap();
+
+        let found = find_divergence(&board, 3, |path, depth| {
+            Ok::<_, ()>(reference(&board, &[], path, depth))
+        });
+
+        assert_eq!(found, Ok(None));
+    }
+
+    #[test]
+    pub fn finds_extra_move() {
+        let board = Board::load_fen(STARTPOS.to_owned()).unwrap();
+        let bug = moves("e2e4 d7d5 e4d5");
+
+        let found = find_divergence(&board, 3, |path, depth| {
+            Ok::<_, ()>(reference(&board, &bug, path, depth))
+        })
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(found.path, moves("e2e4 d7d5"));
+        assert_eq!(
+            found.fen,
+            "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 0 2"
+        );
+        assert_eq!(found.depth, 1);
+        assert_eq!(found.mismatches, vec![Mismatch::Extra(bug[2])]);
+    }
+
+    #[test]
+    pub fn reports_counts_and_errors() {
+        let board = Board::load_fen(STARTPOS.to_owned()).unwrap();
+
+        // Move lists that agree everywhere but a count that doesn't add up.
+        let found = find_divergence(&board, 2, |path, depth| {
+            let mut divide = reference(&board, &[], path, depth);
+
+            if path.is_empty() {
+                divide[0].1 += 1;
+            }
+
+            Ok::<_, ()>(divide)
+        })
+        .unwrap()
+        .unwrap();
+
+        let (mv, ours) = divide(&board, 2)[0];
+
+        assert_eq!(found.path, vec![]);
+        assert_eq!(
+            found.mismatches,
+            vec![Mismatch::Count {
+                mv,
+                ours,
+                theirs: ours + 1
+            }]
+        );
+
+        assert_eq!(find_divergence(&board, 2, |_, _| Err("gone")), Err("gone"));
+    }
+}
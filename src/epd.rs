@@ -0,0 +1,497 @@
+//! Extended Position Description: a position without clocks followed by
+//! opcodes, one record per line. Used by test suites such as WAC, STS and
+//! perftsuite.epd.
+
+use std::str::FromStr;
+
+use crate::{
+    board::{Board, FenError},
+    r#move::Move,
+    san::SanError,
+};
+
+/// Opcodes whose operand is free text and is always written quoted.
+const STRING_OPCODES: [&str; 11] = [
+    "id", "c0", "c1", "c2", "c3", "c4", "c5", "c6", "c7", "c8", "c9",
+];
+
+/// Why [Epd::parse] rejected a record, with byte offsets like [FenError].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EpdError {
+    Fen(FenError),
+    /// An operation without its opcode, e.g. `;;`.
+    MissingOpcode(usize),
+    UnterminatedString(usize),
+    /// Opcode and operand, for operands that don't fit the opcode.
+    InvalidOperand(String, String),
+    San(SanError),
+}
+
+impl core::fmt::Display for EpdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Fen(e) => write!(f, "{}", e),
+            Self::MissingOpcode(at) => write!(f, "invalid EPD at {}: missing opcode", at),
+            Self::UnterminatedString(at) => {
+                write!(f, "invalid EPD at {}: unterminated string", at)
+            }
+            Self::InvalidOperand(opcode, operand) => {
+                write!(f, "invalid operand '{}' for {}", operand, opcode)
+            }
+            Self::San(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for EpdError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operation {
+    pub opcode: String,
+    /// Operands with the quotes of string operands removed.
+    pub operands: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Epd {
+    /// The position, clocks come from `hmvc` and `fmvn` (or trailing FEN clocks).
+    pub board: Board,
+    /// Operations in the order they were read.
+    pub operations: Vec<Operation>,
+}
+
+impl Epd {
+    pub fn new(board: Board) -> Self {
+        Self {
+            board,
+            operations: vec![],
+        }
+    }
+
+    /// Parses one EPD record. Full FENs are accepted too, as some suites
+    /// (perftsuite.epd) keep the clock fields.
+    pub fn parse(epd: &str) -> Result<Self, EpdError> {
+        let mut end = field_end(epd, 0, 4);
+        let mut fen = epd[..end].to_owned();
+
+        // Optional FEN clocks, only if both are there.
+        let clocks_end = field_end(epd, end, 2);
+        let clocks = epd[end..clocks_end].split_whitespace().collect::<Vec<_>>();
+
+        if clocks.len() == 2 && clocks.iter().all(|clock| clock.parse::<usize>().is_ok()) {
+            fen = epd[..clocks_end].to_owned();
+            end = clocks_end;
+        }
+
+        let mut result = Self::new(Board::load_fen(fen).map_err(EpdError::Fen)?);
+        result.operations = parse_operations(&epd[end..], end)?;
+
+        for operation in &result.operations {
+            result.check_operation(operation)?;
+        }
+
+        if let Some(halfmove) = result.integer::<usize>("hmvc") {
+            result.board.halfmove_count = halfmove;
+        }
+
+        if let Some(fullmove) = result.integer::<usize>("fmvn") {
+            result.board.move_count = fullmove.max(1);
+        }
+
+        Ok(result)
+    }
+
+    /// Operands that must be numbers or moves are checked when parsing, so
+    /// the accessors don't have to report errors.
+    fn check_operation(&self, operation: &Operation) -> Result<(), EpdError> {
+        let Operation { opcode, operands } = operation;
+        let invalid = |operand: &str| EpdError::InvalidOperand(opcode.clone(), operand.to_owned());
+
+        match opcode.as_str() {
+            "bm" | "am" => {
+                for san in operands {
+                    self.board.parse_san(san).map_err(EpdError::San)?;
+                }
+            }
+            "ce" if !single::<i32>(operands) => return Err(invalid(&operands.join(" "))),
+            "acd" | "dm" | "hmvc" | "fmvn" if !single::<u32>(operands) => {
+                return Err(invalid(&operands.join(" ")));
+            }
+            _ if perft_depth(opcode).is_some() && !single::<u64>(operands) => {
+                return Err(invalid(&operands.join(" ")));
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    pub fn operation(&self, opcode: &str) -> Option<&[String]> {
+        self.operations
+            .iter()
+            .find(|operation| operation.opcode == opcode)
+            .map(|operation| operation.operands.as_slice())
+    }
+
+    /// Adds the operation, or replaces the operands if the opcode is already there.
+    pub fn set_operation(&mut self, opcode: &str, operands: Vec<String>) {
+        match self.operations.iter_mut().find(|op| op.opcode == opcode) {
+            Some(operation) => operation.operands = operands,
+            None => self.operations.push(Operation {
+                opcode: opcode.to_owned(),
+                operands,
+            }),
+        }
+    }
+
+    fn integer<T: FromStr>(&self, opcode: &str) -> Option<T> {
+        self.operation(opcode)?.first()?.parse().ok()
+    }
+
+    fn moves(&self, opcode: &str) -> Vec<Move> {
+        self.operation(opcode)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|san| self.board.parse_san(san).ok())
+            .collect()
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.operation("id")?.first().map(String::as_str)
+    }
+
+    /// The `c0` to `c9` comments.
+    pub fn comment(&self, n: u8) -> Option<&str> {
+        self.operation(&format!("c{}", n))?
+            .first()
+            .map(String::as_str)
+    }
+
+    /// `bm`, the moves a test expects.
+    pub fn best_moves(&self) -> Vec<Move> {
+        self.moves("bm")
+    }
+
+    /// `am`, the moves a test expects to be avoided.
+    pub fn avoid_moves(&self) -> Vec<Move> {
+        self.moves("am")
+    }
+
+    /// `ce`, the evaluation in centipawns for the side to move.
+    pub fn centipawns(&self) -> Option<i32> {
+        self.integer("ce")
+    }
+
+    /// `acd`, the depth of the analysis behind `ce` and `bm`.
+    pub fn analysis_depth(&self) -> Option<u32> {
+        self.integer("acd")
+    }
+
+    /// The perft counts from the `D1`, `D2`, ... opcodes, by depth.
+    pub fn perft(&self) -> Vec<(u32, u64)> {
+        let mut counts = self
+            .operations
+            .iter()
+            .filter_map(|op| Some((perft_depth(&op.opcode)?, op.operands.first()?.parse().ok()?)))
+            .collect::<Vec<_>>();
+
+        counts.sort();
+        counts
+    }
+
+    /// The record as a single EPD line. The board's clocks are written as
+    /// `hmvc` and `fmvn` unless they are 0 and 1 or the operations have them.
+    pub fn to_epd(&self) -> String {
+        let fen = self.board.to_fen();
+        let mut epd = fen.split(' ').take(4).collect::<Vec<_>>().join(" ");
+
+        for Operation { opcode, operands } in &self.operations {
+            write_operation(&mut epd, opcode, operands);
+        }
+
+        let clocks = [
+            ("hmvc", self.board.halfmove_count, 0),
+            ("fmvn", self.board.move_count, 1),
+        ];
+
+        for (opcode, value, default) in clocks {
+            if value != default && self.operation(opcode).is_none() {
+                write_operation(&mut epd, opcode, &[value.to_string()]);
+            }
+        }
+
+        epd
+    }
+}
+
+impl FromStr for Epd {
+    type Err = EpdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl core::fmt::Display for Epd {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_epd())
+    }
+}
+
+/// Appends ` opcode operand...;`, quoting string operands and any operand
+/// that wouldn't read back as a single word.
+fn write_operation(epd: &mut String, opcode: &str, operands: &[String]) {
+    epd.push(' ');
+    epd.push_str(opcode);
+
+    for operand in operands {
+        epd.push(' ');
+
+        if STRING_OPCODES.contains(&opcode)
+            || operand.is_empty()
+            || operand.contains(|c: char| c.is_whitespace() || matches!(c, ';' | '"' | '\\'))
+        {
+            let escaped = operand.replace('\\', "\\\\").replace('"', "\\\"");
+            epd.push_str(&format!("\"{}\"", escaped));
+        } else {
+            epd.push_str(operand);
+        }
+    }
+
+    epd.push(';');
+}
+
+/// Whether `operands` is a single number of type `T`.
+fn single<T: FromStr>(operands: &[String]) -> bool {
+    matches!(operands, [operand] if operand.parse::<T>().is_ok())
+}
+
+/// `n` for the `Dn` perft opcodes.
+fn perft_depth(opcode: &str) -> Option<u32> {
+    opcode
+        .strip_prefix('D')?
+        .parse()
+        .ok()
+        .filter(|depth| *depth > 0)
+}
+
+/// Offset after `count` more whitespace separated fields from `start`. Fields
+/// end at a `;` too, as the first opcode may follow the position without a space.
+fn field_end(s: &str, start: usize, count: usize) -> usize {
+    let mut end = start;
+
+    for _ in 0..count {
+        let field = end + (s[end..].len() - s[end..].trim_start().len());
+
+        if field == s.len() || s[field..].starts_with(';') {
+            break;
+        }
+
+        end = field
+            + s[field..]
+                .find(|c: char| c.is_whitespace() || c == ';')
+                .unwrap_or(s.len() - field);
+    }
+
+    end
+}
+
+/// `opcode operand ...;` operations, operands may be quoted strings.
+fn parse_operations(s: &str, offset: usize) -> Result<Vec<Operation>, EpdError> {
+    let mut operations = vec![];
+    let mut words: Vec<String> = vec![];
+    let mut chars = s.char_indices().peekable();
+
+    let mut finish = |words: &mut Vec<String>, at: usize| {
+        if words.is_empty() {
+            return Err(EpdError::MissingOpcode(offset + at));
+        }
+
+        operations.push(Operation {
+            opcode: words.remove(0),
+            operands: std::mem::take(words),
+        });
+
+        Ok(())
+    };
+
+    // perftsuite.epd puts a `;` in front of the first opcode as well.
+    let mut leading = true;
+
+    while let Some((at, c)) = chars.next() {
+        match c {
+            ';' if leading => leading = false,
+            ';' => finish(&mut words, at)?,
+            '"' => {
+                let mut string = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => string.extend(chars.next().map(|(_, c)| c)),
+                        Some((_, c)) => string.push(c),
+                        None => return Err(EpdError::UnterminatedString(offset + at)),
+                    }
+                }
+
+                words.push(string);
+                leading = false;
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut word = c.to_string();
+
+                while let Some((_, c)) = chars.next_if(|(_, c)| !c.is_whitespace() && *c != ';') {
+                    word.push(c);
+                }
+
+                words.push(word);
+                leading = false;
+            }
+        }
+    }
+
+    // The `;` after the last operation is often left out.
+    if !words.is_empty() {
+        finish(&mut words, s.len())?;
+    }
+
+    Ok(operations)
+}
+
+#[cfg(test)]
+mod epd_tests {
+    use super::*;
+    use crate::square::Square;
+
+    #[test]
+    pub fn test_suite_record() {
+        let epd = Epd::parse(
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\"; c0 \"mate in 3\";",
+        )
+        .unwrap();
+
+        assert_eq!(epd.id(), Some("WAC.001"));
+        assert_eq!(epd.comment(0), Some("mate in 3"));
+        assert_eq!(epd.comment(1), None);
+        assert_eq!(epd.best_moves().len(), 1);
+        assert_eq!(epd.best_moves()[0].target_square, Square::G6);
+        assert!(epd.avoid_moves().is_empty());
+        assert_eq!(epd.board.move_count, 1);
+        assert_eq!(
+            epd.to_epd(),
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\"; c0 \"mate in 3\";"
+        );
+    }
+
+    #[test]
+    pub fn perft_record() {
+        let epd =
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 ;D1 20 ;D2 400 ;D3 8902"
+                .parse::<Epd>()
+                .unwrap();
+
+        assert_eq!(epd.perft(), [(1, 20), (2, 400), (3, 8902)]);
+        assert_eq!(
+            epd.to_epd(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - D1 20; D2 400; D3 8902;"
+        );
+
+        let epd = Epd::parse("4k3/8/8/8/8/8/8/4K2R w K - 3 40;D1 15").unwrap();
+        assert_eq!(epd.board.halfmove_count, 3);
+        assert_eq!(epd.board.move_count, 40);
+        assert_eq!(epd.perft(), [(1, 15)]);
+
+        // Clocks from the FEN fields come back as opcodes.
+        assert_eq!(
+            epd.to_epd(),
+            "4k3/8/8/8/8/8/8/4K2R w K - D1 15; hmvc 3; fmvn 40;"
+        );
+        assert_eq!(Epd::parse(&epd.to_epd()).unwrap().board, epd.board);
+    }
+
+    #[test]
+    pub fn string_escapes() {
+        let mut epd = Epd::parse("4k3/8/8/8/8/8/8/4K3 w - -").unwrap();
+        epd.set_operation("id", vec!["say \"hi\"".to_owned()]);
+        epd.set_operation("c0", vec!["C:\\suites\\".to_owned()]);
+
+        assert_eq!(
+            epd.to_epd(),
+            r#"4k3/8/8/8/8/8/8/4K3 w - - id "say \"hi\""; c0 "C:\\suites\\";"#
+        );
+        assert_eq!(Epd::parse(&epd.to_epd()).unwrap(), epd);
+    }
+
+    #[test]
+    pub fn analysis_opcodes() {
+        let mut epd = Epd::parse(
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - am Ke2 Qe2; ce -12; acd 20; hmvc 2; fmvn 3; xyz \"a b\" c",
+        )
+        .unwrap();
+
+        assert_eq!(epd.avoid_moves().len(), 2);
+        assert_eq!(epd.centipawns(), Some(-12));
+        assert_eq!(epd.analysis_depth(), Some(20));
+        assert_eq!(epd.board.halfmove_count, 2);
+        assert_eq!(epd.board.move_count, 3);
+        assert_eq!(
+            epd.operation("xyz"),
+            Some(&["a b".to_owned(), "c".to_owned()][..])
+        );
+
+        epd.set_operation("acd", vec!["21".to_owned()]);
+        epd.set_operation("id", vec!["opening".to_owned()]);
+        assert_eq!(epd.analysis_depth(), Some(21));
+
+        let reparsed = Epd::parse(&epd.to_epd()).unwrap();
+        assert_eq!(reparsed, epd);
+    }
+
+    #[test]
+    pub fn errors() {
+        assert!(matches!(Epd::parse("8/8/8/8 w - -"), Err(EpdError::Fen(_))));
+        assert_eq!(
+            Epd::parse("4k3/8/8/8/8/8/8/4K3 w - - id \"open"),
+            Err(EpdError::UnterminatedString(29))
+        );
+        assert_eq!(
+            Epd::parse("4k3/8/8/8/8/8/8/4K3 w - - id \"x\";;"),
+            Err(EpdError::MissingOpcode(33))
+        );
+        assert_eq!(
+            Epd::parse("4k3/8/8/8/8/8/8/4K3 w - - ce high;"),
+            Err(EpdError::InvalidOperand("ce".to_owned(), "high".to_owned()))
+        );
+
+        // Operands have to fit the type their accessor returns.
+        assert_eq!(
+            Epd::parse("4k3/8/8/8/8/8/8/4K3 w - - ce 3000000000;"),
+            Err(EpdError::InvalidOperand(
+                "ce".to_owned(),
+                "3000000000".to_owned()
+            ))
+        );
+        assert_eq!(
+            Epd::parse("4k3/8/8/8/8/8/8/4K3 w - - acd -1;"),
+            Err(EpdError::InvalidOperand("acd".to_owned(), "-1".to_owned()))
+        );
+        assert_eq!(
+            Epd::parse("4k3/8/8/8/8/8/8/4K3 w - - hmvc 5000000000;"),
+            Err(EpdError::InvalidOperand(
+                "hmvc".to_owned(),
+                "5000000000".to_owned()
+            ))
+        );
+        assert_eq!(
+            Epd::parse("4k3/8/8/8/8/8/8/4K3 w - - ce -3000;")
+                .unwrap()
+                .centipawns(),
+            Some(-3000)
+        );
+        assert_eq!(
+            Epd::parse("4k3/8/8/8/8/8/8/4K3 w - - bm Qh5;"),
+            Err(EpdError::San(SanError::Illegal("Qh5".to_owned())))
+        );
+    }
+}
@@ -0,0 +1,353 @@
+//! Static evaluation: material and piece-square tables, in centipawns.
+
+use crate::{
+    board::Board,
+    piece::{Color, Piece},
+    square::*,
+};
+
+/// Material value in centipawns.
+pub fn piece_value(piece: Piece) -> i32 {
+    match piece {
+        Piece::Pawn => 100,
+        Piece::Knight => 320,
+        Piece::Bishop => 330,
+        Piece::Rook => 500,
+        Piece::Queen => 900,
+        Piece::King => 0,
+    }
+}
+
+/// Game phase weight of a piece, 24 for all of them on the board.
+fn phase_weight(piece: Piece) -> i32 {
+    match piece {
+        Piece::Knight | Piece::Bishop => 1,
+        Piece::Rook => 2,
+        Piece::Queen => 4,
+        Piece::Pawn | Piece::King => 0,
+    }
+}
+
+const MAX_PHASE: i32 = 24;
+
+// Piece-square tables from White's point of view, written with rank 8 on top
+// so they read like a board.
+
+#[rustfmt::skip]
+const PAWN_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    50, 50, 50, 50, 50, 50, 50, 50,
+    10, 10, 20, 30, 30, 20, 10, 10,
+     5,  5, 10, 25, 25, 10,  5,  5,
+     0,  0,  0, 20, 20,  0,  0,  0,
+     5, -5,-10,  0,  0,-10, -5,  5,
+     5, 10, 10,-20,-20, 10, 10,  5,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+    -50,-40,-30,-30,-30,-30,-40,-50,
+    -40,-20,  0,  0,  0,  0,-20,-40,
+    -30,  0, 10, 15, 15, 10,  0,-30,
+    -30,  5, 15, 20, 20, 15,  5,-30,
+    -30,  0, 15, 20, 20, 15,  0,-30,
+    -30,  5, 10, 15, 15, 10,  5,-30,
+    -40,-20,  0,  5,  5,  0,-20,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+    -20,-10,-10,-10,-10,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10, 10, 10, 10, 10, 10, 10,-10,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -20,-10,-10,-10,-10,-10,-10,-20,
+];
+
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+     5, 10, 10, 10, 10, 10, 10,  5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+    -5,  0,  0,  0,  0,  0,  0, -5,
+     0,  0,  0,  5,  5,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,  0,  0,  0,  0,  0,  0,-10,
+    -10,  0,  5,  5,  5,  5,  0,-10,
+     -5,  0,  5,  5,  5,  5,  0, -5,
+      0,  0,  5,  5,  5,  5,  0, -5,
+    -10,  5,  5,  5,  5,  5,  0,-10,
+    -10,  0,  5,  0,  0,  0,  0,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+
+/// Sheltered behind its pawns while there is material to attack it.
+#[rustfmt::skip]
+const KING_MIDDLEGAME_TABLE: [i32; 64] = [
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -30,-40,-40,-50,-50,-40,-40,-30,
+    -20,-30,-30,-40,-40,-30,-30,-20,
+    -10,-20,-20,-20,-20,-20,-20,-10,
+     20, 20,  0,  0,  0,  0, 20, 20,
+     20, 30, 10,  0,  0, 10, 30, 20,
+];
+
+/// Central and active once the board empties.
+#[rustfmt::skip]
+const KING_ENDGAME_TABLE: [i32; 64] = [
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -30,-20,-10,  0,  0,-10,-20,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 30, 40, 40, 30,-10,-30,
+    -30,-10, 20, 30, 30, 20,-10,-30,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -50,-30,-30,-30,-30,-30,-30,-50,
+];
+
+/// Index into the tables above for a piece of `color` on `square`.
+fn table_index(color: Color, square: Square) -> usize {
+    let (file, rank) = (square.file() as usize, square.rank() as usize);
+
+    match color {
+        Color::White => (7 - rank) * 8 + file,
+        Color::Black => rank * 8 + file,
+    }
+}
+
+/// Placement bonus of `piece`, the king's blended by `phase`.
+fn placement(piece: Piece, index: usize, phase: i32) -> i32 {
+    match piece {
+        Piece::Pawn => PAWN_TABLE[index],
+        Piece::Knight => KNIGHT_TABLE[index],
+        Piece::Bishop => BISHOP_TABLE[index],
+        Piece::Rook => ROOK_TABLE[index],
+        Piece::Queen => QUEEN_TABLE[index],
+        Piece::King => {
+            (KING_MIDDLEGAME_TABLE[index] * phase + KING_ENDGAME_TABLE[index] * (MAX_PHASE - phase))
+                / MAX_PHASE
+        }
+    }
+}
+
+/// Parts of the evaluation, reported separately by [Evaluation].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Term {
+    Material,
+    /// Placement of each kind of piece.
+    Pawns,
+    Knights,
+    Bishops,
+    Rooks,
+    Queens,
+    King,
+}
+
+impl Term {
+    pub const ALL: [Term; 7] = [
+        Term::Material,
+        Term::Pawns,
+        Term::Knights,
+        Term::Bishops,
+        Term::Rooks,
+        Term::Queens,
+        Term::King,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Term::Material => "Material",
+            Term::Pawns => "Pawns",
+            Term::Knights => "Knights",
+            Term::Bishops => "Bishops",
+            Term::Rooks => "Rooks",
+            Term::Queens => "Queens",
+            Term::King => "King",
+        }
+    }
+
+    fn placement(piece: Piece) -> Self {
+        match piece {
+            Piece::Pawn => Term::Pawns,
+            Piece::Knight => Term::Knights,
+            Piece::Bishop => Term::Bishops,
+            Piece::Rook => Term::Rooks,
+            Piece::Queen => Term::Queens,
+            Piece::King => Term::King,
+        }
+    }
+}
+
+/// Every term of a position's evaluation for both colors, each from its own
+/// point of view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Evaluation {
+    /// From [MAX_PHASE] with all pieces on the board down to 0 in a pawn ending.
+    pub phase: i32,
+    /// Indexed by term and color.
+    pub terms: [[i32; 2]; Term::ALL.len()],
+}
+
+impl Evaluation {
+    pub fn new(board: &Board) -> Self {
+        let phase = board
+            .squares
+            .iter()
+            .flatten()
+            .map(|(_, piece)| phase_weight(*piece))
+            .sum::<i32>()
+            .min(MAX_PHASE);
+
+        let mut terms = [[0; 2]; Term::ALL.len()];
+
+        for square in Square::ALL {
+            let Some((color, piece)) = board.squares[square as usize] else {
+                continue;
+            };
+
+            terms[Term::Material as usize][color as usize] += piece_value(piece);
+            terms[Term::placement(piece) as usize][color as usize] +=
+                placement(piece, table_index(color, square), phase);
+        }
+
+        Self { phase, terms }
+    }
+
+    /// White's score of `term` less Black's.
+    pub fn term(&self, term: Term) -> i32 {
+        let [white, black] = self.terms[term as usize];
+
+        white - black
+    }
+
+    /// The whole evaluation from White's point of view.
+    pub fn total(&self) -> i32 {
+        Term::ALL.iter().map(|&term| self.term(term)).sum()
+    }
+}
+
+/// A table of the terms in centipawns, with the game phase.
+impl core::fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const RULE: &str = "----------+-------+-------+-------";
+
+        let row = |f: &mut std::fmt::Formatter<'_>, name: &str, white, black, total| {
+            writeln!(
+                f,
+                "{:>9} | {:>5} | {:>5} | {:>5}",
+                name, white, black, total
+            )
+        };
+
+        writeln!(
+            f,
+            "{:>9} | {:>5} | {:>5} | {:>5}",
+            "Term", "White", "Black", "Total"
+        )?;
+        writeln!(f, "{}", RULE)?;
+
+        for term in Term::ALL {
+            let [white, black] = self.terms[term as usize];
+            row(f, term.name(), white, black, self.term(term))?;
+        }
+
+        let [white, black] = [Color::White, Color::Black].map(|color| {
+            self.terms
+                .iter()
+                .map(|scores| scores[color as usize])
+                .sum::<i32>()
+        });
+
+        writeln!(f, "{}", RULE)?;
+        row(f, "Total", white, black, self.total())?;
+        write!(f, "Phase {}/{}", self.phase, MAX_PHASE)
+    }
+}
+
+/// Evaluation of `board` from the side to move's point of view.
+pub fn evaluate(board: &Board) -> i32 {
+    let score = Evaluation::new(board).total();
+
+    match board.turn {
+        Color::White => score,
+        Color::Black => -score,
+    }
+}
+
+#[cfg(test)]
+mod eval_tests {
+    use super::*;
+
+    fn eval(fen: &str) -> i32 {
+        evaluate(&Board::load_fen(fen.to_owned()).unwrap())
+    }
+
+    #[test]
+    pub fn symmetric() {
+        assert_eq!(
+            eval("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+            0
+        );
+
+        // Mirrored positions score the same for the side to move.
+        assert_eq!(
+            eval("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3"),
+            eval("rnbqkb1r/pppp1ppp/5n2/4p3/4P3/2N5/PPPP1PPP/R1BQKBNR b KQkq - 2 3")
+        );
+    }
+
+    #[test]
+    pub fn material_and_placement() {
+        // An extra queen, seen from both sides.
+        let white = eval("4k3/8/8/8/8/8/8/3QK3 w - - 0 1");
+        assert!(white > 800);
+        assert_eq!(eval("4k3/8/8/8/8/8/8/3QK3 b - - 0 1"), -white);
+
+        // A centralised knight beats one in the corner.
+        assert!(eval("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1") > eval("4k3/8/8/8/8/8/8/N3K3 w - - 0 1"));
+
+        // With the board empty the king wants the centre.
+        assert!(eval("8/8/8/4k3/8/8/8/K7 b - - 0 1") > 0);
+    }
+
+    #[test]
+    pub fn terms() {
+        let board = Board::load_fen(
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3".to_owned(),
+        )
+        .unwrap();
+        let evaluation = Evaluation::new(&board);
+
+        assert_eq!(evaluation.phase, MAX_PHASE);
+        assert_eq!(evaluation.terms[Term::Material as usize], [4000, 4000]);
+        assert_eq!(evaluation.term(Term::Pawns), 0);
+        assert_eq!(evaluation.term(Term::Knights), 0);
+        assert_eq!(evaluation.total(), evaluate(&board));
+
+        // Swapping the colors swaps the terms.
+        let mirrored = Evaluation::new(&board.mirrored());
+        for term in Term::ALL {
+            let [white, black] = evaluation.terms[term as usize];
+            assert_eq!(mirrored.terms[term as usize], [black, white]);
+        }
+        assert_eq!(evaluate(&board.mirrored()), evaluate(&board));
+
+        let table = evaluation.to_string();
+        assert!(table.contains(" Material |  4000 |  4000 |     0"));
+        assert!(table.ends_with("Phase 24/24"));
+    }
+}
@@ -0,0 +1,27 @@
+//! Board representation, move generation and the chess notations around them.
+//! The UCI engine binary is built on top of this in `main.rs`.
+
+pub mod bench;
+pub mod bitboard;
+pub mod board;
+pub mod book;
+pub mod divide;
+pub mod epd;
+pub mod eval;
+pub mod hardcoded_moves;
+pub(crate) mod macros;
+pub mod r#move;
+pub mod movegen;
+pub mod options;
+pub mod perft;
+pub mod pgn;
+pub mod piece;
+pub mod san;
+pub mod search;
+pub mod square;
+mod tests;
+pub mod uci;
+pub mod utils;
+pub mod zobrist;
+
+use piece::*;
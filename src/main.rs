@@ -1,33 +1,28 @@
 // A Simple chess engine.
 
 use std::{
-    io::{BufRead, Read},
+    io::BufRead,
     marker::PhantomData,
-    num::NonZero,
-    sync::mpsc::{channel, Receiver, Sender},
-    thread::{self, sleep},
-    time::{Duration, Instant},
+    process::ExitCode,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
 };
 
-use board::{BitBoards, Board};
-
-pub mod bitboard;
-pub mod board;
-pub mod hardcoded_moves;
-pub(crate) mod macros;
-pub mod r#move;
-pub mod piece;
-pub mod square;
-mod tests;
-pub mod uci;
-pub mod utils;
-
-use piece::*;
-use r#move::Move;
-use square::Square;
-
-use uci::{UciCommand, UciFen, UciMove};
-use utils::{perft, print_bitboard};
+use chess_engine::{
+    bench::{self, BenchReport},
+    board::Board,
+    book::Book,
+    eval::Evaluation,
+    options::{OptionKind, OptionRegistry, OptionValue, UciOption},
+    perft::{self, PerftOptions},
+    piece::*,
+    search::{Limits, SearchControl, Searcher},
+    uci::{UciCommand, UciFen, UciGo, UciMove, UciParseError},
+};
 
 const STARTING_FEN: &'static str =
     //"8/3k4/8/5n2/6q1/1r1NK3/8/8 w - - 0 1"
@@ -37,6 +32,41 @@ const STARTING_FEN: &'static str =
 //  "rnbqk1nr/pppppppp/8/2b5/8/8/PPP1P1PP/R3K2R w KQkq - 0 1";
 //"4k3/8/8/2b5/8/8/8/R3K2R w KQ - 0 1"; // NO PAWNS(O-O-O + BLOCKED O-O)
 
+const ENGINE_NAME: &str = env!("CARGO_PKG_NAME");
+const ENGINE_AUTHOR: &str = "the chess-engine developers";
+
+/// Options advertised in reply to `uci`, in that order.
+const ENGINE_OPTIONS: [UciOption; 10] = [
+    UciOption {
+        name: "Hash",
+        kind: OptionKind::Spin { default: 16, min: 1, max: 65536 },
+    },
+    UciOption { name: "Clear Hash", kind: OptionKind::Button },
+    // Perft only, the search runs on a single thread.
+    UciOption {
+        name: "Threads",
+        kind: OptionKind::Spin { default: 1, min: 1, max: 512 },
+    },
+    UciOption {
+        name: "MultiPV",
+        kind: OptionKind::Spin { default: 1, min: 1, max: 256 },
+    },
+    UciOption {
+        name: "Move Overhead",
+        kind: OptionKind::Spin { default: 10, min: 0, max: 5000 },
+    },
+    UciOption { name: "Ponder", kind: OptionKind::Check { default: false } },
+    UciOption { name: "UCI_Chess960", kind: OptionKind::Check { default: false } },
+    UciOption { name: "UCI_ShowWDL", kind: OptionKind::Check { default: false } },
+    // Centipawns, positive values avoid draws.
+    UciOption {
+        name: "Contempt",
+        kind: OptionKind::Spin { default: 0, min: -100, max: 100 },
+    },
+    // An EPD file, see chess_engine::book.
+    UciOption { name: "Book File", kind: OptionKind::String { default: "" } },
+];
+
 #[derive(Debug, Clone)]
 pub enum EngineEvent {
     EndGame,
@@ -44,14 +74,32 @@ pub enum EngineEvent {
     PerftResult {
         depth: u32,
         count: u64,
-        root_nodes: Vec<(Move, u64)>,
+        root_nodes: Vec<(UciMove, u64)>,
+        elapsed: Duration,
+        nps: u64,
+    },
+    /// Reply to `uci`: the option declarations to send before `uciok`.
+    Handshake {
+        options: Vec<String>,
+    },
+    ReadyOk,
+    /// An `info` line from the search, without the leading `info`.
+    Info(String),
+    /// `None` if there was no legal move to play.
+    BestMove {
+        best: Option<UciMove>,
+        ponder: Option<UciMove>,
     },
     Debug(String),
+    /// Output of the non-standard commands, printed as it is.
+    Text(String),
 }
 
 #[derive(Debug, Clone)]
 pub enum EngineControl {
     UciCommand(UciCommand),
+    /// A line that isn't a valid command, and why.
+    Invalid(String, UciParseError),
     Terminate,
     Perft(u32),
 }
@@ -59,8 +107,6 @@ pub enum EngineControl {
 pub fn start_uci() {
     eprintln!("Starting UCI....");
 
-    let stdin = std::io::stdin();
-
     let (ctl, ctl_rx) = channel::<EngineControl>();
     let (evt_tx, evt) = channel::<EngineEvent>();
 
@@ -71,44 +117,88 @@ pub fn start_uci() {
         .spawn(move || UciEngine::run_thread(ctl_rx, evt_tx))
         .expect("failed to start engine thread");
 
-    thread::spawn(move || loop {
-        let mut _lock = stdin.lock();
+    // Only hands commands over, so a running search never keeps it from
+    // reading the next one.
+    let reader_thread = thread::Builder::new()
+        .name("stdin".to_owned())
+        .spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
 
-        let mut input = String::new();
-        _lock.read_line(&mut input).expect("failed to read line");
+                if line.trim().is_empty() {
+                    continue;
+                }
 
-        if let Some(cmd) = UciCommand::try_parse(input) {
-            let exit = cmd.clone() == UciCommand::Quit;
+                let cmd = match UciCommand::try_parse(line.clone()) {
+                    Ok(cmd) => cmd,
+                    Err(e) => match io_ctl.send(EngineControl::Invalid(line, e)) {
+                        Ok(()) => continue,
+                        Err(_) => return,
+                    },
+                };
 
-            io_ctl.send(EngineControl::UciCommand(cmd)).unwrap();
+                let quit = cmd == UciCommand::Quit;
 
-            if exit {
-                break;
+                if io_ctl.send(EngineControl::UciCommand(cmd)).is_err() || quit {
+                    return;
+                }
             }
-        }
-        drop(_lock);
-    });
+
+            // The GUI went away without saying quit.
+            let _ = io_ctl.send(EngineControl::UciCommand(UciCommand::Quit));
+        })
+        .expect("failed to start stdin thread");
 
     for event in evt.iter() {
         match event {
             EngineEvent::Debug(msg) => {
                 println!("info string {}", msg);
             },
-            EngineEvent::PerftResult { depth: _, count, root_nodes } 
+            EngineEvent::Handshake { options } => {
+                println!("id name {} {}", ENGINE_NAME, env!("CARGO_PKG_VERSION"));
+                println!("id author {}", ENGINE_AUTHOR);
+
+                for option in options {
+                    println!("{}", option);
+                }
+
+                println!("uciok");
+            }
+            EngineEvent::ReadyOk => println!("readyok"),
+            EngineEvent::Text(text) => println!("{}", text),
+            EngineEvent::Info(info) => println!("info {}", info),
+            EngineEvent::BestMove { best, ponder } => match (best, ponder) {
+                (Some(best), Some(ponder)) => println!("bestmove {} ponder {}", best, ponder),
+                (Some(best), None) => println!("bestmove {}", best),
+                (None, _) => println!("bestmove 0000"),
+            },
+            EngineEvent::PerftResult { depth: _, count, root_nodes, elapsed, nps } 
                 => {
+                    // Same layout as Stockfish, so the two can be diffed.
                     for node in root_nodes {
-                        println!("{}: {}", node.0.notation_long(), node.1);
+                        println!("{}: {}", node.0, node.1);
                     }
 
                     println!();
-                    println!("Count: {},", count);
+                    println!("Nodes searched: {}", count);
+                    println!();
+
+                    eprintln!("Time: {} ms, NPS: {}", elapsed.as_millis(), nps);
                 }
             _ => {}
         }
     }
 
+    // The events stop once the engine thread has quit and joined the search.
     eprintln!("Waiting for Engine Thread to stop...");
-    while !engine_thread.is_finished() {}
+    engine_thread.join().expect("engine thread panicked");
+    reader_thread.join().expect("stdin thread panicked");
+}
+
+/// A search on its worker thread, which hands the searcher back when done.
+struct RunningSearch {
+    control: Arc<SearchControl>,
+    worker: JoinHandle<Searcher>,
 }
 
 pub struct UciEngine<'a> {
@@ -116,28 +206,59 @@ pub struct UciEngine<'a> {
     board: Board,
     stop: bool,
     _phantom: PhantomData<&'a ()>,
-    is_position_set: bool,
+    /// Set by `debug on`, sends traces as `info string`.
+    debug: bool,
+    options: OptionRegistry,
+    /// Zobrist keys of the positions before `board` in the current game.
+    game: Vec<u64>,
+    /// Taken by the running search, if there is one.
+    searcher: Option<Searcher>,
+    /// Loaded from the Book File option, played from in `go`.
+    book: Option<Book>,
+    search: Option<RunningSearch>,
 }
 
 impl<'a> UciEngine<'a> {
     pub(self) fn run_thread(ctl: Receiver<EngineControl>, evt: Sender<EngineEvent>) {
         let mut instance = Self {
             evt_tx: evt.clone(),
-            board: Board::new(),
+            board: Board::load_fen(STARTING_FEN.to_owned()).expect("valid starting position"),
             stop: false,
             _phantom: PhantomData,
-            is_position_set: false,
+            debug: false,
+            options: OptionRegistry::new(&ENGINE_OPTIONS),
+            game: vec![],
+            searcher: None,
+            book: None,
+            search: None,
         };
 
+        instance.searcher = Some(Searcher::new(instance.options.spin("Hash") as usize));
+
         for control in ctl.iter() {
             match control {
                 EngineControl::Terminate => instance.stop = true,
-                EngineControl::UciCommand(c) => match instance.handle_command(c) {
-                    Ok(()) => {}
-                    Err(e) => evt
-                        .send(EngineEvent::Debug(e.to_string()))
-                        .expect("failed to send error event"),
-                },
+                EngineControl::Invalid(line, e) => {
+                    evt.send(EngineEvent::Debug(format!("error: {} in '{}'", e, line.trim())))
+                        .expect("failed to send error event");
+
+                    // The GUI waits for a bestmove after any `go`, even one we can't read.
+                    let command = line.split_whitespace().next().unwrap_or_default();
+
+                    if command.eq_ignore_ascii_case("go") {
+                        instance.stop_search();
+                        evt.send(EngineEvent::BestMove { best: None, ponder: None })
+                            .expect("failed to send bestmove");
+                    }
+                }
+                EngineControl::UciCommand(c) => {
+                    instance.trace(|| format!("command {:?}", c));
+
+                    if let Err(e) = instance.handle_command(c) {
+                        evt.send(EngineEvent::Debug(e.to_string()))
+                            .expect("failed to send error event");
+                    }
+                }
                 _ => {}
             }
 
@@ -151,170 +272,382 @@ impl<'a> UciEngine<'a> {
         self.evt_tx.send(EngineEvent::Debug(m.to_owned())).unwrap()
     }
 
-    pub fn handle_command(&mut self, cmd: UciCommand) -> Result<(), &str> {
-        match cmd {
-            UciCommand::Perft(ply) => {
-                if !self.is_position_set {
-                    self.print("position not set");
-                    return Ok(());
-                }
-
-                let mut nodes = None;
-
-                let count = utils::perft(&mut self.board, ply, ply, &mut nodes);
-
-                self.evt_tx.send(EngineEvent::PerftResult {
-                    depth: ply,
-                    count,
-                    root_nodes: nodes.unwrap_or(vec![]),
-                }).expect("failed to send perft() result");
-            }
-            UciCommand::Position { fen, moves } => {
-                let fen = fen.unwrap_or(UciFen::new(&STARTING_FEN));
-
-                self.board = Board::load_fen(fen.inner()).ok_or("error: invalid fen")?;
-
-                self.is_position_set = true;
-
-                for mv in moves {
-                    let m = self
-                        .board
-                        .uci_to_board_move(self.board.turn, mv)
-                        .ok_or("error: invalid moves")?;
+    /// Prints `text` as it is, for the non-standard commands.
+    fn text(&self, text: String) {
+        self.evt_tx.send(EngineEvent::Text(text)).expect("failed to send text");
+    }
 
-                    self.board.do_move(m);
-                }
-            }
-            UciCommand::Stop => todo!("UciCommand::Stop, searching is not yet implemented"),
-            _ => {}
+    /// Prints the message only in debug mode, so it's not built otherwise.
+    fn trace(&self, message: impl FnOnce() -> String) {
+        if self.debug {
+            self.print(&message());
         }
+    }
 
-        Ok(())
+    /// Forgets the current game and goes back to the starting position, which
+    /// is also searched if `go` comes before any `position`. Options are kept.
+    fn new_game(&mut self) {
+        self.board = Board::load_fen(STARTING_FEN.to_owned()).expect("valid starting position");
+        self.board.chess960 = self.options.check("UCI_Chess960");
+        self.game.clear();
+        self.searcher().clear();
     }
-}
 
-fn main() {
-    let mut board = Board::load_fen(/*""*/ STARTING_FEN.to_owned()).unwrap();
-
-    /*  println!("==== BOARD INFO ====");
-        println!("Material: {:?}", board.count_material());
-        println!("Turn: {:?}", board.turn);
-        println!("FEN: {:?}", STARTING_FEN);
-        println!("SYNTAX: . = EMPTY, % = ACTIVE");
-
-        println!("==== (PRE) MOVE GENERATION ====");
-        let start = std::time::Instant::now();
-        let moves = board.generate_moves(Color::Black);
-        println!("[Color: {:?}]", Color::Black);
-        println!("[Time: {:?}]\n", Instant::now() - start);
-        println!("[Move: @PreScan]");
-        for (i, m) in moves.iter().enumerate() {
-            let piece = board.squares[m.starting_square as usize]
-                .expect("Board state changed during move generation")
-                .1;
-
-            println!("{}. ({:?}){}", 1 + i, piece, m);
+    /// Stops the running search, if any, and waits for its `bestmove`.
+    fn stop_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.control.stop();
+            search.worker.thread().unpark();
+
+            self.searcher = Some(search.worker.join().expect("search thread panicked"));
         }
+    }
 
-        println!("==== MOVE GENERATION ====");
+    /// The searcher, stopping the search that's using it.
+    fn searcher(&mut self) -> &mut Searcher {
+        self.stop_search();
+        self.searcher.as_mut().expect("searcher not returned by the search thread")
+    }
 
-        let start = std::time::Instant::now();
-        let moves = board.generate_moves(Color::White);
-        println!("[Color: {:?}]", Color::White);
-        println!("[Time: {:?}]\n", Instant::now() - start);
-        println!("[Move: {:?}]", board.move_count);
+    /// Search limits for the side to move. Search moves that aren't legal are
+    /// reported and left out, the GUI still gets its bestmove.
+    fn limits(&self, go: &UciGo) -> Limits {
+        let (time, increment) = match self.board.turn {
+            Color::White => (go.wtime, go.winc),
+            Color::Black => (go.btime, go.binc),
+        };
 
-        for (i, m) in moves.iter().enumerate() {
-            let piece = board.squares[m.starting_square as usize]
-                .expect("Board state changed during move generation")
-                .1;
+        let searchmoves = go
+            .searchmoves
+            .iter()
+            .filter_map(|&mv| {
+                let legal = self
+                    .board
+                    .uci_to_board_move(self.board.turn, mv)
+                    .filter(|&m| self.board.is_legal(m));
+
+                if legal.is_none() {
+                    self.print(&format!("error: illegal search move {} ignored", mv));
+                }
 
-            println!("{}. ({:?}){}", 1 + i, piece, m);
+                legal
+            })
+            .collect();
+
+        Limits {
+            depth: go.depth,
+            nodes: go.nodes,
+            mate: go.mate,
+            movetime: go.movetime.map(Duration::from_millis),
+            time: time.map(Duration::from_millis),
+            increment: Duration::from_millis(increment.unwrap_or(0)),
+            moves_to_go: go.movestogo,
+            move_overhead: Duration::from_millis(self.options.spin("Move Overhead") as u64),
+            infinite: go.infinite,
+            searchmoves,
+        }
+    }
 
-            board.do_move(*m);
-            print_bitboard(board.bitboards.all_pieces(None));
-            board.undo_move(*m);
+    /// Starts searching the current position on a worker thread, which sends
+    /// `info` lines and the `bestmove`.
+    fn go(&mut self, go: UciGo) {
+        let limits = self.limits(&go);
+
+        self.trace(|| match limits.time_budget() {
+            Some((optimum, maximum)) => format!(
+                "time optimum {} ms maximum {} ms",
+                optimum.as_millis(),
+                maximum.as_millis()
+            ),
+            None => "time unlimited".to_owned(),
+        });
 
-            println!()
+        self.stop_search();
+
+        // Book moves are played at once, but not while pondering or analysing.
+        let book_move = self
+            .book
+            .as_ref()
+            .filter(|_| !go.ponder && !go.infinite)
+            .and_then(|book| book.probe(&self.board))
+            .filter(|mv| limits.searchmoves.is_empty() || limits.searchmoves.contains(mv));
+
+        if let Some(mv) = book_move {
+            self.trace(|| format!("book move {}", self.board.board_to_uci_move(mv)));
+            self.evt_tx
+                .send(EngineEvent::BestMove {
+                    best: Some(self.board.board_to_uci_move(mv)),
+                    ponder: None,
+                })
+                .expect("failed to send bestmove");
+
+            return;
         }
 
-        let mut board = Board::load_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_owned()).unwrap();
-    */
+        let mut searcher = self
+            .searcher
+            .take()
+            .expect("searcher not returned by the search thread");
+        searcher.contempt = self.options.spin("Contempt") as i32;
+        searcher.multipv = self.options.spin("MultiPV") as usize;
+        searcher.show_wdl = self.options.check("UCI_ShowWDL");
+
+        let control = Arc::new(SearchControl::new(go.ponder));
+
+        let (board, game, evt, worker_control, debug) = (
+            self.board.clone(),
+            self.game.clone(),
+            self.evt_tx.clone(),
+            control.clone(),
+            self.debug,
+        );
+
+        let worker = thread::Builder::new()
+            .name("search".to_owned())
+            .spawn(move || {
+                let control = worker_control;
+
+                let result = searcher.search(&board, &game, &limits, &control, |info| {
+                    evt.send(EngineEvent::Info(info.to_uci(&board)))
+                        .expect("failed to send search info");
+                });
+
+                if debug {
+                    evt.send(EngineEvent::Debug(format!(
+                        "search depth {} nodes {} {}",
+                        result.depth, result.nodes, result.stats
+                    )))
+                    .expect("failed to send search stats");
+                }
 
-    // board.prepare();
+                // The GUI expects no bestmove before ponderhit or stop, even if
+                // there's nothing left to search.
+                while !control.is_stopped() && (control.is_pondering() || limits.infinite) {
+                    thread::park();
+                }
 
-    /*board.do_move(Move {
-            starting_square: Square::E2,
-            target_square: Square::E4,
-            flag: r#move::MoveFlag::None,
-        });
+                let ponder = result.best.zip(result.ponder).map(|(best, ponder)| {
+                    let mut after = board.clone();
+                    after.do_move(best).expect("best move failed");
+                    after.board_to_uci_move(ponder)
+                });
 
-        board.do_move(Move {
-            starting_square: Square::B8,
-            target_square: Square::A6,
-            flag: r#move::MoveFlag::None,
-        });
+                evt.send(EngineEvent::BestMove {
+                    best: result.best.map(|mv| board.board_to_uci_move(mv)),
+                    ponder,
+                })
+                .expect("failed to send bestmove");
 
-        board.do_move(Move {
-            starting_square: Square::F1,
-            target_square: Square::A6,
-            flag: r#move::MoveFlag::Capture(Piece::Knight),
-        });
-    */
-    //print_bitboard(board.bitboards.all_pieces(None));
+                searcher
+            })
+            .expect("failed to start search thread");
 
-    println!();
+        self.search = Some(RunningSearch { control, worker });
+    }
 
-    let mv = Move {
-        starting_square: Square::C2,
-        target_square: Square::C4,
-        flag: r#move::MoveFlag::None,
-    };
+    /// Perft settings from the Threads and Hash options.
+    fn perft_options(&self) -> PerftOptions {
+        PerftOptions {
+            threads: self.options.spin("Threads") as usize,
+            hash_mb: self.options.spin("Hash") as usize,
+        }
+    }
 
-    board.do_move(mv);
+    pub fn handle_command(&mut self, cmd: UciCommand) -> Result<(), String> {
+        match cmd {
+            UciCommand::Uci => {
+                self.evt_tx
+                    .send(EngineEvent::Handshake {
+                        options: self
+                            .options
+                            .declarations()
+                            .map(|option| option.to_string())
+                            .collect(),
+                    })
+                    .expect("failed to send handshake");
+            }
+            UciCommand::IsReady => {
+                self.evt_tx
+                    .send(EngineEvent::ReadyOk)
+                    .expect("failed to send readyok");
+            }
+            UciCommand::UciNewGame => self.new_game(),
+            UciCommand::Perft(ply) => {
+                let report = perft::perft(&self.board, ply, self.perft_options());
 
-    print_bitboard(board.bitboards.all_pieces(None));
-    println!();
+                self.evt_tx.send(EngineEvent::PerftResult {
+                    depth: ply,
+                    count: report.nodes,
+                    root_nodes: report
+                        .root_nodes
+                        .iter()
+                        .map(|&(mv, count)| (self.board.board_to_uci_move(mv), count))
+                        .collect(),
+                    elapsed: report.elapsed,
+                    nps: report.nps(),
+                }).expect("failed to send perft() result");
+            }
+            UciCommand::Position { fen, moves } => {
+                let fen = fen.unwrap_or(UciFen::new(&STARTING_FEN));
 
-    board.undo_move(mv);
+                // Set up aside, so that a bad move keeps the previous position.
+                let mut board = Board::load_fen(fen.inner()).map_err(|e| format!("error: {}", e))?;
+                board.chess960 = self.options.check("UCI_Chess960");
 
-    // board = Board::load_fen("8/8/2k5/8/8/8/1K1P1r2/8 w - - 0 1".to_owned()).unwrap();
+                let mut game = vec![];
 
-    print_bitboard(board.bitboards.all_pieces(None));
+                for mv in moves {
+                    let m = board
+                        .uci_to_board_move(board.turn, mv)
+                        .ok_or("error: invalid moves")?;
 
-    /*  board.do_move(Move {
-        starting_square: Square::D2,
-        target_square: Square::D3,
-        flag: r#move::MoveFlag::None,
-    });
+                    if !board.is_legal(m) {
+                        return Err(format!("error: illegal move {}", m.notation_long()));
+                    }
 
-    board.do_move(Move {
-        starting_square: Square::A7,
-        target_square: Square::A5,
-        flag: r#move::MoveFlag::None,
-    });
+                    game.push(board.zobrist());
+                    board.do_move(m).map_err(|e| format!("error: {}", e))?;
+                }
 
-        board.do_move(Move {
-            starting_square: Square::D1,
-            target_square: Square::A4,
-            flag: r#move::MoveFlag::None,
-        });
-    */
-    print_bitboard(board.pieces(Color::White));
+                self.board = board;
+                self.game = game;
+
+                self.trace(|| {
+                    format!("position {} hash {:016x}", self.board.to_fen(), self.board.zobrist())
+                });
+            }
+            UciCommand::SetOption { name, value } => {
+                let (name, value) = self
+                    .options
+                    .set(&name, value.as_deref())
+                    .map_err(|e| format!("error: {}", e))?;
+
+                // Options are read when they're needed, apart from the ones the
+                // current board and the searcher's table keep.
+                match (name, value) {
+                    ("UCI_Chess960", &OptionValue::Check(chess960)) => {
+                        self.board.chess960 = chess960
+                    }
+                    ("Hash", &OptionValue::Spin(mb)) => self.searcher().resize(mb as usize),
+                    ("Clear Hash", _) => self.searcher().clear(),
+                    ("Book File", OptionValue::String(path)) => {
+                        self.book = None;
+
+                        if !path.is_empty() {
+                            let book = Book::load(path).map_err(|e| format!("error: {}", e))?;
+                            self.book = Some(book);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            UciCommand::Go(go) => self.go(go),
+            UciCommand::PonderHit => {
+                if let Some(search) = &self.search {
+                    search.control.ponderhit();
+                    search.worker.thread().unpark();
+                }
+            }
+            UciCommand::Debug(on) => self.debug = on,
+            UciCommand::D => {
+                let checkers = self
+                    .board
+                    .checkers()
+                    .active_squares()
+                    .iter()
+                    .map(|square| square.to_string().to_ascii_lowercase())
+                    .collect::<Vec<_>>();
+
+                self.text(format!(
+                    "{}\nFen: {}\nKey: {:016x}\nCheckers: {}",
+                    self.board.to_ascii(),
+                    self.board.to_fen(),
+                    self.board.zobrist(),
+                    checkers.join(" ")
+                ));
+            }
+            UciCommand::Eval => {
+                let evaluation = Evaluation::new(&self.board);
+
+                self.text(format!(
+                    "{}\nEvaluation: {:+} (white side)",
+                    evaluation,
+                    evaluation.total()
+                ));
+            }
+            UciCommand::Flip => {
+                self.board = self.board.mirrored();
+                // The earlier positions don't lead to this one.
+                self.game.clear();
+            }
+            UciCommand::Moves => {
+                let moves = self.board.generate_moves(self.board.turn);
+
+                let mut text = moves
+                    .iter()
+                    .map(|&mv| {
+                        format!("{} {}\n", self.board.board_to_uci_move(mv), self.board.to_san(mv))
+                    })
+                    .collect::<String>();
+                text.push_str(&format!("Legal moves: {}", moves.len()));
+
+                self.text(text);
+            }
+            UciCommand::Bench(depth) => {
+                self.stop_search();
+                self.text(run_bench(depth).to_string());
+            }
+            UciCommand::Quit => {
+                self.stop_search();
+                self.stop = true;
+            }
+            UciCommand::Stop => {
+                // The search sends its bestmove, and is joined when the searcher
+                // is needed again.
+                if let Some(search) = &self.search {
+                    search.control.stop();
+                    search.worker.thread().unpark();
+                }
+            }
+        }
 
-    let mut start = Instant::now();
-    let v = perft(&mut board, 4, 4, &mut None);
-    let time = Instant::now() - start;
+        Ok(())
+    }
+}
 
-    start = Instant::now();
-    println!(
-        "[Nodes: {}]\n[Time]\n  Total: {:?}\n  Avg. Per Node: {:?}\n",
-        v,
-        time,
-        time.div_f32(v as _)
-    );
+/// Searches the bench positions with the default options, whatever they're
+/// set to, so the node count only depends on the search. Progress goes to
+/// stderr.
+fn run_bench(depth: Option<u32>) -> BenchReport {
+    let hash_mb = OptionRegistry::new(&ENGINE_OPTIONS).spin("Hash") as usize;
+    let positions = bench::POSITIONS.len();
+
+    bench::bench(
+        &bench::POSITIONS,
+        depth.unwrap_or(bench::DEPTH),
+        hash_mb,
+        |i, result| eprintln!("Position {}/{}: {} nodes", i + 1, positions, result.nodes),
+    )
+}
 
-    println!();
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] => start_uci(),
+        ["bench"] => println!("{}", run_bench(None)),
+        ["bench", depth] => match depth.parse() {
+            Ok(depth) => println!("{}", run_bench(Some(depth))),
+            Err(_) => {
+                eprintln!("error: invalid bench depth '{}'", depth);
+                return ExitCode::from(2);
+            }
+        },
+        _ => {
+            eprintln!("usage: {} [bench [depth]]", ENGINE_NAME);
+            return ExitCode::from(2);
+        }
+    }
 
-    start_uci();
+    ExitCode::SUCCESS
 }
@@ -1,6 +1,6 @@
 use crate::{piece::Piece, square::Square};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Move {
     pub starting_square: Square,
     pub target_square: Square,
@@ -19,6 +19,85 @@ impl Move {
 }
 
 
+/// A [Move] packed into 16 bits for search tables (TT entries, killers, history).
+///
+/// Layout: bits 0-5 starting square, bits 6-11 target square, bits 12-15 flag.
+/// The captured piece of a [MoveFlag::Capture] is not stored, use
+/// [crate::board::Board::unpack_move] to recover the full [Move]. A promotion
+/// to a pawn or king can't be played and packs to [PackedMove::NONE].
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct PackedMove(pub u16);
+
+impl PackedMove {
+    /// Empty slot, never produced by packing a real move.
+    pub const NONE: Self = Self(0);
+
+    pub const FLAG_NONE: u8 = 0;
+    pub const FLAG_CASTLE_SHORT: u8 = 1;
+    pub const FLAG_CASTLE_LONG: u8 = 2;
+    pub const FLAG_NULL_MOVE: u8 = 3;
+    pub const FLAG_CAPTURE: u8 = 4;
+    pub const FLAG_UNTARGETED: u8 = 5;
+    pub const FLAG_EN_PASSANT: u8 = 6;
+    /// `FLAG_PROMOTION` + 0 to 3 for Bishop, Knight, Rook and Queen.
+    pub const FLAG_PROMOTION: u8 = 8;
+
+    pub const fn new(starting_square: Square, target_square: Square, flag: u8) -> Self {
+        Self((starting_square as u16) | ((target_square as u16) << 6) | ((flag as u16) << 12))
+    }
+
+    pub fn starting_square(self) -> Square {
+        Square::index((self.0 & 0x3f) as usize)
+    }
+
+    pub fn target_square(self) -> Square {
+        Square::index(((self.0 >> 6) & 0x3f) as usize)
+    }
+
+    pub const fn flag(self) -> u8 {
+        (self.0 >> 12) as u8
+    }
+
+    pub fn is_none(self) -> bool {
+        self == Self::NONE
+    }
+
+    /// Promotion piece, if this is a promotion.
+    pub fn promotion(self) -> Option<Piece> {
+        match self.flag().checked_sub(Self::FLAG_PROMOTION)? {
+            0 => Some(Piece::Bishop),
+            1 => Some(Piece::Knight),
+            2 => Some(Piece::Rook),
+            3 => Some(Piece::Queen),
+            _ => None,
+        }
+    }
+}
+
+impl From<Move> for PackedMove {
+    fn from(mv: Move) -> Self {
+        let flag = match mv.flag {
+            MoveFlag::None => Self::FLAG_NONE,
+            MoveFlag::Castle(CastlingMethod::Short) => Self::FLAG_CASTLE_SHORT,
+            MoveFlag::Castle(CastlingMethod::Long) => Self::FLAG_CASTLE_LONG,
+            MoveFlag::NullMove => Self::FLAG_NULL_MOVE,
+            MoveFlag::Capture(_) => Self::FLAG_CAPTURE,
+            MoveFlag::Untargeted => Self::FLAG_UNTARGETED,
+            MoveFlag::EnPassant(_) => Self::FLAG_EN_PASSANT,
+            MoveFlag::Promotion(p) => match p {
+                Piece::Bishop => Self::FLAG_PROMOTION,
+                Piece::Knight => Self::FLAG_PROMOTION + 1,
+                Piece::Rook => Self::FLAG_PROMOTION + 2,
+                Piece::Queen => Self::FLAG_PROMOTION + 3,
+                Piece::Pawn | Piece::King => return Self::NONE,
+            },
+        };
+
+        Self::new(mv.starting_square, mv.target_square, flag)
+    }
+}
+
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MoveFlag {
@@ -64,3 +143,67 @@ impl core::fmt::Display for Move {
         }
     }
 }
+
+#[cfg(test)]
+mod packed_move_tests {
+    use super::*;
+    use crate::board::Board;
+
+    #[test]
+    pub fn size() {
+        assert_eq!(core::mem::size_of::<PackedMove>(), 2);
+    }
+
+    #[test]
+    pub fn roundtrip_generated_moves() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "4k3/1P6/8/3pP3/8/8/8/4K3 w - d6 0 1",
+        ] {
+            let board = Board::load_fen(fen.to_owned()).unwrap();
+
+            for mv in board.generate_moves(board.turn) {
+                let packed = PackedMove::from(mv);
+
+                assert!(!packed.is_none());
+                assert_eq!(board.unpack_move(packed), Some(mv), "{} in {}", mv, fen);
+            }
+        }
+    }
+
+    #[test]
+    pub fn promotion_pieces() {
+        for piece in Piece::PROMOTIONS {
+            let mv = Move {
+                starting_square: Square::B7,
+                target_square: Square::B8,
+                flag: MoveFlag::Promotion(piece),
+            };
+
+            assert_eq!(PackedMove::from(mv).promotion(), Some(piece));
+        }
+    }
+
+    #[test]
+    pub fn unplayable_promotions() {
+        for piece in [Piece::Pawn, Piece::King] {
+            let mv = Move {
+                starting_square: Square::B7,
+                target_square: Square::B8,
+                flag: MoveFlag::Promotion(piece),
+            };
+
+            assert!(PackedMove::from(mv).is_none());
+        }
+    }
+
+    #[test]
+    pub fn capture_needs_board() {
+        let board = Board::load_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1".to_owned()).unwrap();
+
+        let packed = PackedMove::new(Square::E1, Square::E2, PackedMove::FLAG_CAPTURE);
+
+        assert_eq!(board.unpack_move(packed), None);
+    }
+}
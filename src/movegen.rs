@@ -1 +1,175 @@
-    
\ No newline at end of file
+//! Attack sets for every piece type, independent of any [crate::board::Board] state.
+
+use crate::{
+    bitboard::BitBoard,
+    hardcoded_moves::{KING_MOVES, KNIGHT_MOVES},
+    piece::{Color, Piece},
+    square::*,
+};
+
+/// Ray directions as (file, rank) steps, the first four point towards higher square indices.
+const DIRECTIONS: [(i8, i8); 8] = [
+    (0, 1),
+    (1, 0),
+    (1, 1),
+    (-1, 1),
+    (0, -1),
+    (-1, 0),
+    (-1, -1),
+    (1, -1),
+];
+
+const ROOK_DIRECTIONS: [usize; 4] = [0, 1, 4, 5];
+const BISHOP_DIRECTIONS: [usize; 4] = [2, 3, 6, 7];
+
+const RAYS: [[u64; Square::NUM]; 8] = build_rays();
+
+const fn build_rays() -> [[u64; Square::NUM]; 8] {
+    let mut rays = [[0; Square::NUM]; 8];
+
+    let mut dir = 0;
+    while dir < 8 {
+        let mut square = 0;
+        while square < Square::NUM {
+            let (mut file, mut rank) = ((square % 8) as i8, (square / 8) as i8);
+
+            loop {
+                file += DIRECTIONS[dir].0;
+                rank += DIRECTIONS[dir].1;
+
+                if file < 0 || file > 7 || rank < 0 || rank > 7 {
+                    break;
+                }
+
+                rays[dir][square] |= 1 << (rank * 8 + file);
+            }
+
+            square += 1;
+        }
+
+        dir += 1;
+    }
+
+    rays
+}
+
+/// Squares a slider sees in one direction, up to and including the first blocker.
+fn ray_attacks(dir: usize, square: Square, occupancy: BitBoard) -> u64 {
+    let ray = RAYS[dir][square as usize];
+    let blockers = ray & occupancy.0;
+
+    if blockers == 0 {
+        return ray;
+    }
+
+    let first = match dir < 4 {
+        true => blockers.trailing_zeros(),
+        false => 63 - blockers.leading_zeros(),
+    };
+
+    ray ^ RAYS[dir][first as usize]
+}
+
+pub fn rook_attacks(square: Square, occupancy: BitBoard) -> BitBoard {
+    BitBoard(
+        ROOK_DIRECTIONS
+            .iter()
+            .fold(0, |acc, dir| acc | ray_attacks(*dir, square, occupancy)),
+    )
+}
+
+pub fn bishop_attacks(square: Square, occupancy: BitBoard) -> BitBoard {
+    BitBoard(
+        BISHOP_DIRECTIONS
+            .iter()
+            .fold(0, |acc, dir| acc | ray_attacks(*dir, square, occupancy)),
+    )
+}
+
+pub fn queen_attacks(square: Square, occupancy: BitBoard) -> BitBoard {
+    rook_attacks(square, occupancy) | bishop_attacks(square, occupancy)
+}
+
+pub fn knight_attacks(square: Square) -> BitBoard {
+    KNIGHT_MOVES[square as usize]
+}
+
+pub fn king_attacks(square: Square) -> BitBoard {
+    KING_MOVES[square as usize]
+}
+
+/// Squares a pawn of `color` standing on `square` attacks.
+pub fn pawn_attacks(square: Square, color: Color) -> BitBoard {
+    let pawn = square.bitboard().0;
+    let (not_a, not_h) = (!File::A.bitboard().0, !File::H.bitboard().0);
+
+    BitBoard(match color {
+        Color::White => ((pawn << 7) & not_h) | ((pawn << 9) & not_a),
+        Color::Black => ((pawn >> 9) & not_h) | ((pawn >> 7) & not_a),
+    })
+}
+
+/// Attack set of any piece, pawns attack as `color`.
+pub fn piece_attacks(piece: Piece, color: Color, square: Square, occupancy: BitBoard) -> BitBoard {
+    match piece {
+        Piece::Pawn => pawn_attacks(square, color),
+        Piece::Knight => knight_attacks(square),
+        Piece::Bishop => bishop_attacks(square, occupancy),
+        Piece::Rook => rook_attacks(square, occupancy),
+        Piece::Queen => queen_attacks(square, occupancy),
+        Piece::King => king_attacks(square),
+    }
+}
+
+#[cfg(test)]
+mod attack_tests {
+    use super::*;
+
+    #[test]
+    pub fn rook_blocked() {
+        let occupancy = Square::D6.bitboard() | Square::F4.bitboard() | Square::B4.bitboard();
+
+        assert_eq!(
+            rook_attacks(Square::D4, occupancy).active_squares(),
+            vec![
+                Square::D1,
+                Square::D2,
+                Square::D3,
+                Square::B4,
+                Square::C4,
+                Square::E4,
+                Square::F4,
+                Square::D5,
+                Square::D6,
+            ]
+        );
+    }
+
+    #[test]
+    pub fn bishop_does_not_wrap() {
+        assert_eq!(
+            bishop_attacks(Square::H1, BitBoard::EMPTY).active_squares(),
+            vec![
+                Square::G2,
+                Square::F3,
+                Square::E4,
+                Square::D5,
+                Square::C6,
+                Square::B7,
+                Square::A8,
+            ]
+        );
+    }
+
+    #[test]
+    pub fn pawns_on_edges() {
+        assert_eq!(
+            pawn_attacks(Square::A2, Color::White).active_squares(),
+            vec![Square::B3]
+        );
+        assert_eq!(
+            pawn_attacks(Square::H7, Color::Black).active_squares(),
+            vec![Square::G6]
+        );
+    }
+}
@@ -0,0 +1,319 @@
+//! UCI engine options: their declarations as sent in reply to `uci`, and the
+//! values set with `setoption`.
+
+/// The type of an option, with its default and allowed values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptionKind {
+    Check {
+        default: bool,
+    },
+    Spin {
+        default: i64,
+        min: i64,
+        max: i64,
+    },
+    Combo {
+        default: &'static str,
+        vars: &'static [&'static str],
+    },
+    /// Triggers an action, has no value.
+    Button,
+    String {
+        default: &'static str,
+    },
+}
+
+/// An option the engine declares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UciOption {
+    pub name: &'static str,
+    pub kind: OptionKind,
+}
+
+/// The declaration as sent in reply to `uci`, e.g.
+/// `option name Hash type spin default 16 min 1 max 65536`.
+impl core::fmt::Display for UciOption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "option name {} type ", self.name)?;
+
+        match self.kind {
+            OptionKind::Check { default } => write!(f, "check default {}", default),
+            OptionKind::Spin { default, min, max } => {
+                write!(f, "spin default {} min {} max {}", default, min, max)
+            }
+            OptionKind::Combo { default, vars } => {
+                write!(f, "combo default {}", default)?;
+
+                for var in vars {
+                    write!(f, " var {}", var)?;
+                }
+
+                Ok(())
+            }
+            OptionKind::Button => write!(f, "button"),
+            OptionKind::String { default } => match default {
+                "" => write!(f, "string default <empty>"),
+                default => write!(f, "string default {}", default),
+            },
+        }
+    }
+}
+
+/// The current value of an option.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionValue {
+    Check(bool),
+    Spin(i64),
+    /// One of the vars, spelled as declared.
+    Combo(&'static str),
+    Button,
+    String(String),
+}
+
+impl OptionValue {
+    fn default_of(kind: OptionKind) -> Self {
+        match kind {
+            OptionKind::Check { default } => Self::Check(default),
+            OptionKind::Spin { default, .. } => Self::Spin(default),
+            OptionKind::Combo { default, .. } => Self::Combo(default),
+            OptionKind::Button => Self::Button,
+            OptionKind::String { default } => Self::String(default.to_owned()),
+        }
+    }
+}
+
+/// Why [OptionRegistry::set] rejected a `setoption`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OptionError {
+    Unknown(String),
+    /// Option name, for options other than buttons set without a value.
+    MissingValue(&'static str),
+    /// Option name and the rejected value.
+    InvalidValue(&'static str, String),
+}
+
+impl core::fmt::Display for OptionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unknown(name) => write!(f, "unknown option '{}'", name),
+            Self::MissingValue(name) => write!(f, "missing value for option '{}'", name),
+            Self::InvalidValue(name, value) => {
+                write!(f, "invalid value '{}' for option '{}'", value, name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OptionError {}
+
+/// Declared options and their current values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OptionRegistry {
+    options: Vec<(UciOption, OptionValue)>,
+}
+
+impl OptionRegistry {
+    /// All options set to their defaults.
+    pub fn new(options: &[UciOption]) -> Self {
+        Self {
+            options: options
+                .iter()
+                .map(|option| (*option, OptionValue::default_of(option.kind)))
+                .collect(),
+        }
+    }
+
+    pub fn declarations(&self) -> impl Iterator<Item = &UciOption> {
+        self.options.iter().map(|(option, _)| option)
+    }
+
+    /// Option names are case-insensitive, as in the UCI protocol.
+    fn find(&self, name: &str) -> Option<usize> {
+        self.options
+            .iter()
+            .position(|(option, _)| option.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&OptionValue> {
+        Some(&self.options[self.find(name)?].1)
+    }
+
+    /// Value of a check option, false if there is none called `name`.
+    pub fn check(&self, name: &str) -> bool {
+        matches!(self.get(name), Some(OptionValue::Check(true)))
+    }
+
+    /// Value of a spin option, 0 if there is none called `name`.
+    pub fn spin(&self, name: &str) -> i64 {
+        match self.get(name) {
+            Some(OptionValue::Spin(value)) => *value,
+            _ => 0,
+        }
+    }
+
+    /// Value of a combo or string option, empty if there is none called `name`.
+    pub fn string(&self, name: &str) -> &str {
+        match self.get(name) {
+            Some(OptionValue::Combo(value)) => value,
+            Some(OptionValue::String(value)) => value,
+            _ => "",
+        }
+    }
+
+    /// Applies `setoption name <name> value <value>`, returning the option
+    /// that changed and its new value.
+    pub fn set(
+        &mut self,
+        name: &str,
+        value: Option<&str>,
+    ) -> Result<(&'static str, &OptionValue), OptionError> {
+        let index = self
+            .find(name)
+            .ok_or_else(|| OptionError::Unknown(name.to_owned()))?;
+
+        let option = self.options[index].0;
+        let invalid = |value: &str| OptionError::InvalidValue(option.name, value.to_owned());
+
+        let parsed = match (option.kind, value.map(str::trim)) {
+            (OptionKind::Button, _) => OptionValue::Button,
+            (_, None) => return Err(OptionError::MissingValue(option.name)),
+            (OptionKind::Check { .. }, Some(value)) => match value.to_ascii_lowercase().as_str() {
+                "true" => OptionValue::Check(true),
+                "false" => OptionValue::Check(false),
+                _ => return Err(invalid(value)),
+            },
+            (OptionKind::Spin { min, max, .. }, Some(value)) => match value.parse::<i64>() {
+                Ok(spin) if (min..=max).contains(&spin) => OptionValue::Spin(spin),
+                _ => return Err(invalid(value)),
+            },
+            (OptionKind::Combo { vars, .. }, Some(value)) => OptionValue::Combo(
+                vars.iter()
+                    .find(|var| var.eq_ignore_ascii_case(value))
+                    .ok_or_else(|| invalid(value))?,
+            ),
+            (OptionKind::String { .. }, Some("<empty>")) => OptionValue::String(String::new()),
+            (OptionKind::String { .. }, Some(value)) => OptionValue::String(value.to_owned()),
+        };
+
+        self.options[index].1 = parsed;
+
+        Ok((option.name, &self.options[index].1))
+    }
+}
+
+#[cfg(test)]
+mod options_tests {
+    use super::*;
+
+    const OPTIONS: [UciOption; 5] = [
+        UciOption {
+            name: "Hash",
+            kind: OptionKind::Spin {
+                default: 16,
+                min: 1,
+                max: 1024,
+            },
+        },
+        UciOption {
+            name: "Ponder",
+            kind: OptionKind::Check { default: false },
+        },
+        UciOption {
+            name: "Style",
+            kind: OptionKind::Combo {
+                default: "Normal",
+                vars: &["Solid", "Normal", "Risky"],
+            },
+        },
+        UciOption {
+            name: "Clear Hash",
+            kind: OptionKind::Button,
+        },
+        UciOption {
+            name: "Book File",
+            kind: OptionKind::String { default: "" },
+        },
+    ];
+
+    #[test]
+    pub fn declarations() {
+        let registry = OptionRegistry::new(&OPTIONS);
+
+        assert_eq!(
+            registry
+                .declarations()
+                .map(|option| option.to_string())
+                .collect::<Vec<_>>(),
+            [
+                "option name Hash type spin default 16 min 1 max 1024",
+                "option name Ponder type check default false",
+                "option name Style type combo default Normal var Solid var Normal var Risky",
+                "option name Clear Hash type button",
+                "option name Book File type string default <empty>",
+            ]
+        );
+    }
+
+    #[test]
+    pub fn set_values() {
+        let mut registry = OptionRegistry::new(&OPTIONS);
+
+        assert_eq!(registry.spin("Hash"), 16);
+        assert_eq!(
+            registry.set("hash", Some("64")),
+            Ok(("Hash", &OptionValue::Spin(64)))
+        );
+        assert_eq!(registry.spin("Hash"), 64);
+
+        registry.set("Ponder", Some("TRUE")).unwrap();
+        assert!(registry.check("ponder"));
+
+        registry.set("Style", Some("risky")).unwrap();
+        assert_eq!(registry.string("Style"), "Risky");
+
+        assert_eq!(
+            registry.set("Clear Hash", None),
+            Ok(("Clear Hash", &OptionValue::Button))
+        );
+
+        registry
+            .set("Book File", Some("/books/main book.bin"))
+            .unwrap();
+        assert_eq!(registry.string("Book File"), "/books/main book.bin");
+        registry.set("Book File", Some("<empty>")).unwrap();
+        assert_eq!(registry.string("Book File"), "");
+    }
+
+    #[test]
+    pub fn invalid_values() {
+        let mut registry = OptionRegistry::new(&OPTIONS);
+
+        assert_eq!(
+            registry.set("Hash", Some("0")),
+            Err(OptionError::InvalidValue("Hash", "0".to_owned()))
+        );
+        assert_eq!(
+            registry.set("Hash", Some("lots")),
+            Err(OptionError::InvalidValue("Hash", "lots".to_owned()))
+        );
+        assert_eq!(
+            registry.set("Hash", None),
+            Err(OptionError::MissingValue("Hash"))
+        );
+        assert_eq!(
+            registry.set("Ponder", Some("yes")),
+            Err(OptionError::InvalidValue("Ponder", "yes".to_owned()))
+        );
+        assert_eq!(
+            registry.set("Style", Some("Wild")),
+            Err(OptionError::InvalidValue("Style", "Wild".to_owned()))
+        );
+        assert_eq!(
+            registry.set("Threads", Some("2")),
+            Err(OptionError::Unknown("Threads".to_owned()))
+        );
+
+        // Rejected values leave the old ones in place.
+        assert_eq!(registry, OptionRegistry::new(&OPTIONS));
+    }
+}
@@ -0,0 +1,238 @@
+//! Fast perft for benchmarking and checking move generation. Leaf moves are
+//! counted without being played, subtree counts are cached by Zobrist key and
+//! depth, and the root moves are shared out between threads.
+//!
+//! [crate::utils::perft] is the plain recursive version to check this against.
+
+use std::{
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{board::Board, r#move::Move};
+
+/// Settings for [perft].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PerftOptions {
+    /// Worker threads, at least one is always used.
+    pub threads: usize,
+    /// Size of the subtree cache in MiB. 0 disables it.
+    pub hash_mb: usize,
+}
+
+impl Default for PerftOptions {
+    fn default() -> Self {
+        Self {
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            hash_mb: 16,
+        }
+    }
+}
+
+/// Result of a [perft] run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PerftReport {
+    pub depth: u32,
+    pub nodes: u64,
+    /// Node count below every legal root move, in generation order.
+    pub root_nodes: Vec<(Move, u64)>,
+    pub elapsed: Duration,
+}
+
+impl PerftReport {
+    /// Nodes per second.
+    pub fn nps(&self) -> u64 {
+        match self.elapsed.as_micros() {
+            0 => self.nodes,
+            micros => (self.nodes as u128 * 1_000_000 / micros) as u64,
+        }
+    }
+}
+
+/// Counts the leaf nodes `depth` plies below `board`.
+pub fn perft(board: &Board, depth: u32, options: PerftOptions) -> PerftReport {
+    let start = Instant::now();
+
+    if depth == 0 {
+        return PerftReport {
+            depth,
+            nodes: 1,
+            root_nodes: vec![],
+            elapsed: start.elapsed(),
+        };
+    }
+
+    let moves = board.generate_moves(board.turn);
+    let table = PerftTable::new(options.hash_mb);
+
+    let next = AtomicUsize::new(0);
+    let counts = moves.iter().map(|_| AtomicU64::new(0)).collect::<Vec<_>>();
+
+    thread::scope(|scope| {
+        for _ in 0..options.threads.clamp(1, moves.len().max(1)) {
+            scope.spawn(|| {
+                let mut board = board.clone();
+
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(&mv) = moves.get(i) else { break };
+
+                    board.do_move(mv).expect("generated move failed");
+                    let nodes = count(&mut board, depth - 1, &table);
+                    board.undo_move(mv).expect("failed to undo generated move");
+
+                    counts[i].store(nodes, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+
+    let root_nodes = moves
+        .into_iter()
+        .zip(counts)
+        .map(|(mv, nodes)| (mv, nodes.into_inner()))
+        .collect::<Vec<_>>();
+
+    PerftReport {
+        depth,
+        nodes: root_nodes.iter().map(|(_, nodes)| nodes).sum(),
+        root_nodes,
+        elapsed: start.elapsed(),
+    }
+}
+
+fn count(board: &mut Board, depth: u32, table: &PerftTable) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    // Bulk counting: the leaves below a depth 1 node are just its legal moves.
+    if depth == 1 {
+        return board.generate_moves(board.turn).len() as u64;
+    }
+
+    let key = board.zobrist();
+
+    if let Some(nodes) = table.probe(key, depth) {
+        return nodes;
+    }
+
+    let mut nodes = 0;
+
+    for mv in board.generate_moves(board.turn) {
+        board.do_move(mv).expect("generated move failed");
+        nodes += count(board, depth - 1, table);
+        board.undo_move(mv).expect("failed to undo generated move");
+    }
+
+    table.store(key, depth, nodes);
+
+    nodes
+}
+
+/// Subtree counts shared by all threads, always replacing. Each entry holds
+/// `key ^ data` next to `data` (data being the count and depth), so an entry
+/// torn by two threads writing at once fails the key check instead of
+/// returning a wrong count.
+struct PerftTable {
+    entries: Vec<[AtomicU64; 2]>,
+}
+
+impl PerftTable {
+    /// Depths are stored in the low byte of the data word.
+    const DEPTH_BITS: u32 = 8;
+
+    fn new(hash_mb: usize) -> Self {
+        let len = (hash_mb << 20) / std::mem::size_of::<[AtomicU64; 2]>();
+
+        // A power of two, so indexing is a mask.
+        let len = match len {
+            0 => 0,
+            len => 1 << len.ilog2(),
+        };
+
+        Self {
+            entries: (0..len)
+                .map(|_| [AtomicU64::new(0), AtomicU64::new(0)])
+                .collect(),
+        }
+    }
+
+    /// Mixes the depth in, so one position at different depths uses different entries.
+    fn slot(&self, key: u64, depth: u32) -> Option<(u64, &[AtomicU64; 2])> {
+        let key = key ^ (depth as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+        let index = key as usize & self.entries.len().checked_sub(1)?;
+
+        Some((key, &self.entries[index]))
+    }
+
+    fn probe(&self, key: u64, depth: u32) -> Option<u64> {
+        let (key, [check, data]) = self.slot(key, depth)?;
+        let (check, data) = (check.load(Ordering::Relaxed), data.load(Ordering::Relaxed));
+
+        (check ^ data == key && data & ((1 << Self::DEPTH_BITS) - 1) == depth as u64)
+            .then_some(data >> Self::DEPTH_BITS)
+    }
+
+    fn store(&self, key: u64, depth: u32, nodes: u64) {
+        let Some((key, [check, data])) = self.slot(key, depth) else {
+            return;
+        };
+
+        let value = nodes << Self::DEPTH_BITS | depth as u64;
+
+        check.store(key ^ value, Ordering::Relaxed);
+        data.store(value, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod perft_tests {
+    use super::*;
+    use crate::utils;
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    #[test]
+    pub fn matches_plain_perft() {
+        let mut board = Board::load_fen(KIWIPETE.to_owned()).unwrap();
+
+        for depth in 0..=3 {
+            let mut root_nodes = None;
+            let nodes = utils::perft(&mut board, depth, depth, &mut root_nodes);
+
+            for options in [
+                PerftOptions {
+                    threads: 1,
+                    hash_mb: 0,
+                },
+                PerftOptions {
+                    threads: 3,
+                    hash_mb: 1,
+                },
+            ] {
+                let report = perft(&board, depth, options);
+
+                assert_eq!(report.nodes, nodes, "depth {} with {:?}", depth, options);
+                assert_eq!(report.root_nodes, root_nodes.clone().unwrap_or_default());
+            }
+        }
+    }
+
+    #[test]
+    pub fn table() {
+        let table = PerftTable::new(1);
+
+        assert_eq!(table.probe(42, 3), None);
+
+        table.store(42, 3, 97862);
+        assert_eq!(table.probe(42, 3), Some(97862));
+        assert_eq!(table.probe(42, 4), None);
+        assert_eq!(table.probe(43, 3), None);
+
+        let table = PerftTable::new(0);
+        table.store(42, 3, 97862);
+        assert_eq!(table.probe(42, 3), None);
+    }
+}
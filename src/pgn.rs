@@ -0,0 +1,1145 @@
+//! Portable Game Notation. [PgnReader] streams games out of any [BufRead],
+//! one game at a time, so large databases never have to fit in memory.
+//! [Game::to_pgn] writes them back.
+
+use std::{io::BufRead, time::Duration};
+
+use crate::{
+    board::{Board, FenError},
+    piece::Color,
+    r#move::Move,
+    san::SanError,
+};
+
+const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    /// Game still running, abandoned or result unknown (`*`).
+    #[default]
+    Unknown,
+}
+
+impl GameResult {
+    pub fn parse(s: &str) -> Option<Self> {
+        Some(match s {
+            "1-0" => Self::WhiteWins,
+            "0-1" => Self::BlackWins,
+            "1/2-1/2" => Self::Draw,
+            "*" => Self::Unknown,
+            _ => None?,
+        })
+    }
+}
+
+impl core::fmt::Display for GameResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::WhiteWins => "1-0",
+            Self::BlackWins => "0-1",
+            Self::Draw => "1/2-1/2",
+            Self::Unknown => "*",
+        })
+    }
+}
+
+/// A `[%eval]` comment, from White's point of view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eval {
+    Centipawns(i32),
+    /// Mate in this many moves, negative if Black mates.
+    Mate(i32),
+}
+
+impl Eval {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.strip_prefix('#') {
+            Some(mate) => Some(Self::Mate(mate.parse().ok()?)),
+            None => Some(Self::Centipawns(
+                (s.parse::<f64>().ok()? * 100.0).round() as i32
+            )),
+        }
+    }
+}
+
+impl core::fmt::Display for Eval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Centipawns(cp) => write!(f, "{:.2}", *cp as f64 / 100.0),
+            Self::Mate(moves) => write!(f, "#{}", moves),
+        }
+    }
+}
+
+/// `[%clk]` time as `h:mm:ss`, with fractions of a second if there are any.
+fn parse_clock(s: &str) -> Option<Duration> {
+    let mut parts = s.split(':');
+    let (hours, minutes, seconds) = (parts.next()?, parts.next()?, parts.next()?);
+
+    if parts.next().is_some() {
+        return None;
+    }
+
+    let seconds = hours.parse::<u64>().ok()? as f64 * 3600.0
+        + minutes.parse::<u64>().ok()? as f64 * 60.0
+        + seconds.parse::<f64>().ok()?;
+
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+fn format_clock(clock: Duration) -> String {
+    let seconds = clock.as_secs();
+    let tenths = clock.subsec_millis() / 100;
+
+    let mut out = format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    );
+
+    if tenths != 0 {
+        out.push_str(&format!(".{}", tenths));
+    }
+
+    out
+}
+
+/// A move of a game or variation with the annotations around it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameMove {
+    pub mv: Move,
+    /// Comment in front of the move, only used at the start of a game or variation.
+    pub starting_comment: Option<String>,
+    /// Numeric annotation glyphs, `!` and `?` suffixes are stored as their NAGs.
+    pub nags: Vec<u8>,
+    pub comment: Option<String>,
+    /// Evaluation after the move, read from and written as `[%eval]`.
+    pub eval: Option<Eval>,
+    /// Clock of the side that moved, read from and written as `[%clk]`.
+    pub clock: Option<Duration>,
+    /// Alternatives to this move, each starting from the position before it.
+    pub variations: Vec<Vec<GameMove>>,
+}
+
+impl GameMove {
+    pub fn new(mv: Move) -> Self {
+        Self {
+            mv,
+            starting_comment: None,
+            nags: vec![],
+            comment: None,
+            eval: None,
+            clock: None,
+            variations: vec![],
+        }
+    }
+
+    /// A variation without annotations, e.g. from an engine's principal variation.
+    pub fn line(moves: impl IntoIterator<Item = Move>) -> Vec<Self> {
+        moves.into_iter().map(Self::new).collect()
+    }
+
+    /// Takes the `[%eval]` and `[%clk]` commands out of `comment`, returning the rest.
+    fn read_commands(&mut self, comment: &str) -> String {
+        let mut rest = String::new();
+        let mut remaining = comment;
+
+        while let Some(start) = remaining.find("[%") {
+            let Some(end) = remaining[start..].find(']') else {
+                break;
+            };
+
+            let command = &remaining[start + 2..start + end];
+            let (name, value) = command.split_once(' ').unwrap_or((command, ""));
+
+            let known = match name {
+                "eval" => Eval::parse(value.trim()).map(|eval| self.eval = Some(eval)),
+                "clk" => parse_clock(value.trim()).map(|clock| self.clock = Some(clock)),
+                _ => None,
+            };
+
+            rest.push_str(&remaining[..start]);
+
+            if known.is_none() {
+                rest.push_str(&remaining[start..=start + end]);
+            }
+
+            remaining = &remaining[start + end + 1..];
+        }
+
+        rest.push_str(remaining);
+        rest.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// The text of the comment after the move, commands first.
+    fn full_comment(&self) -> Option<String> {
+        let mut parts = vec![];
+
+        if let Some(eval) = self.eval {
+            parts.push(format!("[%eval {}]", eval));
+        }
+
+        if let Some(clock) = self.clock {
+            parts.push(format!("[%clk {}]", format_clock(clock)));
+        }
+
+        parts.extend(self.comment.clone());
+
+        (!parts.is_empty()).then(|| parts.join(" "))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Game {
+    /// Tag pairs in the order they were read.
+    pub tags: Vec<(String, String)>,
+    /// Position before the first move, from the `FEN` tag if there is one.
+    pub start: Board,
+    pub moves: Vec<GameMove>,
+    pub result: GameResult,
+}
+
+impl Game {
+    pub fn new(start: Board) -> Self {
+        Self {
+            tags: vec![],
+            start,
+            moves: vec![],
+            result: GameResult::Unknown,
+        }
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn mainline(&self) -> impl Iterator<Item = Move> + '_ {
+        self.moves.iter().map(|mv| mv.mv)
+    }
+
+    /// The game as PGN: the Seven Tag Roster, `SetUp` and `FEN` for other start
+    /// positions unless there's a `FEN` tag, then the other tags, then the
+    /// movetext in SAN wrapped at 80 characters.
+    pub fn to_pgn(&self) -> String {
+        const SEVEN_TAG_ROSTER: [(&str, &str); 7] = [
+            ("Event", "?"),
+            ("Site", "?"),
+            ("Date", "????.??.??"),
+            ("Round", "?"),
+            ("White", "?"),
+            ("Black", "?"),
+            ("Result", "*"),
+        ];
+
+        let mut pgn = String::new();
+        let result = self.result.to_string();
+
+        for (name, default) in SEVEN_TAG_ROSTER {
+            let value = match name {
+                "Result" => &result,
+                _ => self.tag(name).unwrap_or(default),
+            };
+
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, escape_tag(value)));
+        }
+
+        // Readers assume the standard start position without these.
+        let fen = self.start.to_fen();
+
+        if fen != STARTING_FEN && self.tag("FEN").is_none() {
+            pgn.push_str(&format!("[SetUp \"1\"]\n[FEN \"{}\"]\n", fen));
+        }
+
+        for (name, value) in &self.tags {
+            if SEVEN_TAG_ROSTER.iter().all(|(roster, _)| roster != name) {
+                pgn.push_str(&format!("[{} \"{}\"]\n", name, escape_tag(value)));
+            }
+        }
+
+        pgn.push('\n');
+
+        let mut tokens = vec![];
+        write_movetext(&self.start, &self.moves, &mut tokens);
+        tokens.push(result);
+
+        let mut line_length = 0;
+
+        for word in tokens.iter().flat_map(|token| token.split_whitespace()) {
+            if line_length != 0 && line_length + 1 + word.len() > 80 {
+                pgn.push('\n');
+                line_length = 0;
+            } else if line_length != 0 {
+                pgn.push(' ');
+                line_length += 1;
+            }
+
+            pgn.push_str(word);
+            line_length += word.len();
+        }
+
+        pgn.push('\n');
+        pgn
+    }
+
+    /// The start position followed by the position after each mainline move.
+    pub fn boards(&self) -> Vec<Board> {
+        let mut board = self.start.clone();
+        let mut boards = vec![board.clone()];
+
+        for mv in self.mainline() {
+            board
+                .do_move(mv)
+                .expect("game moves were checked when they were read");
+            boards.push(board.clone());
+        }
+
+        boards
+    }
+}
+
+fn escape_tag(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// A `{...}` comment. PGN has no escape for `}`, so it is left out.
+fn comment_token(comment: &str) -> String {
+    format!("{{{}}}", comment.replace('}', ""))
+}
+
+/// Movetext tokens of `moves` played from `board`, variations in parentheses.
+fn write_movetext(board: &Board, moves: &[GameMove], tokens: &mut Vec<String>) {
+    let mut board = board.clone();
+    // Black's moves need their number after anything that interrupts the moves.
+    let mut interrupted = true;
+
+    for game_move in moves {
+        if let Some(comment) = &game_move.starting_comment {
+            tokens.push(comment_token(comment));
+            interrupted = true;
+        }
+
+        match board.turn {
+            Color::White => tokens.push(format!("{}.", board.move_count)),
+            Color::Black if interrupted => tokens.push(format!("{}...", board.move_count)),
+            Color::Black => {}
+        }
+
+        tokens.push(board.to_san(game_move.mv));
+        tokens.extend(game_move.nags.iter().map(|nag| format!("${}", nag)));
+        interrupted = false;
+
+        if let Some(comment) = game_move.full_comment() {
+            tokens.push(comment_token(&comment));
+            interrupted = true;
+        }
+
+        for variation in &game_move.variations {
+            let mut variation_tokens = vec![];
+            write_movetext(&board, variation, &mut variation_tokens);
+
+            if let Some(last) = variation_tokens.last_mut() {
+                last.push(')');
+                variation_tokens[0].insert(0, '(');
+
+                tokens.extend(variation_tokens);
+                interrupted = true;
+            }
+        }
+
+        board
+            .do_move(game_move.mv)
+            .expect("game moves must be legal in their position");
+    }
+}
+
+/// Where in the input an error was found, lines and columns count from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PgnError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: PgnErrorKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PgnErrorKind {
+    Io(String),
+    UnexpectedChar(char),
+    UnterminatedTag,
+    UnterminatedComment,
+    /// `(` without a move to be an alternative to, `)` without `(`, or a
+    /// game ending inside a variation.
+    UnbalancedVariation,
+    Fen(FenError),
+    San(SanError),
+}
+
+impl core::fmt::Display for PgnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid PGN at {}:{}: ", self.line, self.column)?;
+
+        match &self.kind {
+            PgnErrorKind::Io(e) => write!(f, "read failed: {}", e),
+            PgnErrorKind::UnexpectedChar(c) => write!(f, "unexpected character '{}'", c),
+            PgnErrorKind::UnterminatedTag => f.write_str("unterminated tag pair"),
+            PgnErrorKind::UnterminatedComment => f.write_str("unterminated comment"),
+            PgnErrorKind::UnbalancedVariation => f.write_str("unbalanced variation"),
+            PgnErrorKind::Fen(e) => write!(f, "{}", e),
+            PgnErrorKind::San(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    Move(String),
+    Open,
+    Close,
+    Result(GameResult),
+    Eof,
+}
+
+/// Characters of the input with their line and column, read a line at a time.
+struct Source<R> {
+    reader: R,
+    line: Vec<char>,
+    column: usize,
+    line_number: usize,
+}
+
+impl<R: BufRead> Source<R> {
+    fn position(&self) -> (usize, usize) {
+        (self.line_number, self.column + 1)
+    }
+
+    fn error(&self, kind: PgnErrorKind) -> PgnError {
+        let (line, column) = self.position();
+        PgnError { line, column, kind }
+    }
+
+    /// Next character, reading more input at the end of a line. Files that
+    /// aren't valid UTF-8 (old databases are often Latin-1) are read lossily.
+    fn peek(&mut self) -> Result<Option<char>, PgnError> {
+        while self.column >= self.line.len() {
+            let mut buf = vec![];
+
+            let read = self
+                .reader
+                .read_until(b'\n', &mut buf)
+                .map_err(|e| self.error(PgnErrorKind::Io(e.to_string())))?;
+
+            if read == 0 {
+                return Ok(None);
+            }
+
+            self.line = String::from_utf8_lossy(&buf).chars().collect();
+            self.column = 0;
+            self.line_number += 1;
+
+            if self.line_number == 1 && self.line.first() == Some(&'\u{feff}') {
+                self.column = 1;
+            }
+
+            // Lines starting with `%` are escaped and ignored.
+            if self.line.get(self.column) == Some(&'%') {
+                self.column = self.line.len();
+            }
+        }
+
+        Ok(Some(self.line[self.column]))
+    }
+
+    fn bump(&mut self) {
+        self.column += 1;
+    }
+
+    /// Skips the rest of the current line, for `;` comments.
+    fn rest_of_line(&mut self) -> String {
+        let rest = self.line[self.column..].iter().collect::<String>();
+        self.column = self.line.len();
+        rest.trim_end().to_owned()
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> Result<String, PgnError> {
+        let mut out = String::new();
+
+        while let Some(c) = self.peek()? {
+            if !f(c) {
+                break;
+            }
+
+            out.push(c);
+            self.bump();
+        }
+
+        Ok(out)
+    }
+}
+
+fn is_symbol(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_+#=:-/.!?".contains(c)
+}
+
+/// NAGs of the `!`, `?`, `!!`, `??`, `!?` and `?!` move suffixes.
+fn suffix_nag(suffix: &str) -> Option<u8> {
+    Some(match suffix {
+        "!" => 1,
+        "?" => 2,
+        "!!" => 3,
+        "??" => 4,
+        "!?" => 5,
+        "?!" => 6,
+        _ => None?,
+    })
+}
+
+/// Streams the games of a PGN file.
+///
+/// A game that fails to parse is returned as an error, reading then resumes
+/// with the next game.
+pub struct PgnReader<R> {
+    source: Source<R>,
+    peeked: Option<(Token, (usize, usize))>,
+    result: GameResult,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            source: Source {
+                reader,
+                line: vec![],
+                column: 0,
+                line_number: 0,
+            },
+            peeked: None,
+            result: GameResult::Unknown,
+        }
+    }
+
+    fn error_at(at: (usize, usize), kind: PgnErrorKind) -> PgnError {
+        PgnError {
+            line: at.0,
+            column: at.1,
+            kind,
+        }
+    }
+
+    /// The next token and where it starts.
+    fn next_token(&mut self) -> Result<(Token, (usize, usize)), PgnError> {
+        if let Some(peeked) = self.peeked.take() {
+            return Ok(peeked);
+        }
+
+        loop {
+            let Some(c) = self.source.peek()? else {
+                return Ok((Token::Eof, self.source.position()));
+            };
+
+            let at = self.source.position();
+
+            if c.is_whitespace() {
+                self.source.bump();
+                continue;
+            }
+
+            let token = match c {
+                '[' => {
+                    self.source.bump();
+                    self.tag()?
+                }
+                '{' => {
+                    self.source.bump();
+
+                    let comment = self.source.take_while(|c| c != '}')?;
+
+                    match self.source.peek()? {
+                        Some(_) => self.source.bump(),
+                        None => return Err(Self::error_at(at, PgnErrorKind::UnterminatedComment)),
+                    }
+
+                    Token::Comment(comment.trim().to_owned())
+                }
+                ';' => {
+                    self.source.bump();
+                    Token::Comment(self.source.rest_of_line().trim().to_owned())
+                }
+                '$' => {
+                    self.source.bump();
+
+                    match self.source.take_while(|c| c.is_ascii_digit())?.parse() {
+                        Ok(nag) => Token::Nag(nag),
+                        Err(_) => return Err(Self::error_at(at, PgnErrorKind::UnexpectedChar(c))),
+                    }
+                }
+                '(' => {
+                    self.source.bump();
+                    Token::Open
+                }
+                ')' => {
+                    self.source.bump();
+                    Token::Close
+                }
+                '*' => {
+                    self.source.bump();
+                    Token::Result(GameResult::Unknown)
+                }
+                c if is_symbol(c) => match self.symbol()? {
+                    Some(token) => token,
+                    None => continue,
+                },
+                c => {
+                    self.source.bump();
+                    return Err(Self::error_at(at, PgnErrorKind::UnexpectedChar(c)));
+                }
+            };
+
+            return Ok((token, at));
+        }
+    }
+
+    /// The inside of `[Name "value"]`, with `\"` and `\\` escapes.
+    fn tag(&mut self) -> Result<Token, PgnError> {
+        let unterminated = |source: &Source<R>| source.error(PgnErrorKind::UnterminatedTag);
+
+        self.source.take_while(|c| c == ' ' || c == '\t')?;
+        let name = self
+            .source
+            .take_while(|c| c.is_ascii_alphanumeric() || c == '_')?;
+        self.source.take_while(|c| c == ' ' || c == '\t')?;
+
+        if name.is_empty() || self.source.peek()? != Some('"') {
+            self.source.rest_of_line();
+            return Err(unterminated(&self.source));
+        }
+
+        self.source.bump();
+
+        let mut value = String::new();
+
+        loop {
+            // Tags never span lines, `peek` would read the next one.
+            if self.source.column >= self.source.line.len() {
+                return Err(unterminated(&self.source));
+            }
+
+            match self.source.line[self.source.column] {
+                '"' => break,
+                '\\' if self.source.column + 1 < self.source.line.len() => {
+                    self.source.bump();
+                    value.push(self.source.line[self.source.column]);
+                }
+                '\n' | '\r' => return Err(unterminated(&self.source)),
+                c => value.push(c),
+            }
+
+            self.source.bump();
+        }
+
+        self.source.bump();
+        self.source.take_while(|c| c == ' ' || c == '\t')?;
+
+        if self.source.peek()? != Some(']') {
+            self.source.rest_of_line();
+            return Err(unterminated(&self.source));
+        }
+
+        self.source.bump();
+
+        Ok(Token::Tag(name, value))
+    }
+
+    /// Moves, move numbers (skipped, `None`) and results.
+    fn symbol(&mut self) -> Result<Option<Token>, PgnError> {
+        let symbol = self.source.take_while(is_symbol)?;
+
+        if let Some(result) = GameResult::parse(&symbol) {
+            return Ok(Some(Token::Result(result)));
+        }
+
+        // `12.`, `12...`, or glued to the move as in `1.e4`.
+        let digits = symbol.chars().take_while(|c| c.is_ascii_digit()).count();
+        let rest = &symbol[digits..];
+
+        let san = match rest.trim_start_matches('.') {
+            san if san.len() < rest.len() || (digits > 0 && rest.is_empty()) => san,
+            _ => symbol.as_str(),
+        };
+
+        if san.is_empty() {
+            return Ok(None);
+        }
+
+        // Suffix annotations on their own, e.g. `e4 !?`.
+        if let Some(nag) = suffix_nag(san) {
+            return Ok(Some(Token::Nag(nag)));
+        }
+
+        Ok(Some(Token::Move(san.to_owned())))
+    }
+
+    /// Reads the game after the tags, or skips to the next one on errors.
+    fn read_game(&mut self) -> Result<Option<Game>, PgnError> {
+        let mut tags = vec![];
+        let mut game_start = None;
+
+        loop {
+            let (token, at) = self.next_token()?;
+            game_start.get_or_insert(at);
+
+            match token {
+                Token::Tag(name, value) => tags.push((name, value)),
+                Token::Eof if tags.is_empty() => return Ok(None),
+                token => {
+                    self.peeked = Some((token, at));
+                    break;
+                }
+            }
+        }
+
+        let start = match tags.iter().find(|(name, _)| name == "FEN") {
+            Some((_, fen)) => Board::load_fen(fen.clone())
+                .map_err(|e| Self::error_at(game_start.unwrap(), PgnErrorKind::Fen(e)))?,
+            None => Board::load_fen(STARTING_FEN.to_owned()).expect("valid starting position"),
+        };
+
+        self.result = GameResult::Unknown;
+
+        let moves = self.read_line(start.clone(), false)?;
+
+        Ok(Some(Game {
+            tags,
+            start,
+            moves,
+            result: self.result,
+        }))
+    }
+
+    /// Moves up to the end of the game, or up to `)` for variations.
+    fn read_line(&mut self, mut board: Board, variation: bool) -> Result<Vec<GameMove>, PgnError> {
+        let mut line: Vec<GameMove> = vec![];
+        let mut before = None;
+        let mut pending_comment: Option<String> = None;
+
+        loop {
+            let (token, at) = self.next_token()?;
+
+            match token {
+                Token::Move(san) => {
+                    let annotation = san.trim_end_matches(['!', '?']);
+                    let nag = suffix_nag(&san[annotation.len()..]);
+
+                    let mv = board
+                        .parse_san(annotation)
+                        .map_err(|e| Self::error_at(at, PgnErrorKind::San(e)))?;
+
+                    before = Some(board.clone());
+                    board.do_move(mv).expect("parse_san returns legal moves");
+
+                    let mut game_move = GameMove::new(mv);
+                    game_move.starting_comment = pending_comment.take();
+                    game_move.nags.extend(nag);
+
+                    line.push(game_move);
+                }
+                Token::Comment(comment) => {
+                    let comment = match line.last_mut() {
+                        Some(last) => last.read_commands(&comment),
+                        None => comment,
+                    };
+
+                    if comment.is_empty() {
+                        continue;
+                    }
+
+                    let target = match line.last_mut() {
+                        Some(last) => &mut last.comment,
+                        None => &mut pending_comment,
+                    };
+
+                    match target {
+                        Some(existing) => {
+                            existing.push(' ');
+                            existing.push_str(&comment);
+                        }
+                        None => *target = Some(comment),
+                    }
+                }
+                Token::Nag(nag) => {
+                    if let Some(last) = line.last_mut() {
+                        last.nags.push(nag);
+                    }
+                }
+                Token::Open => {
+                    let Some(before) = before.clone() else {
+                        return Err(Self::error_at(at, PgnErrorKind::UnbalancedVariation));
+                    };
+
+                    let variation = self.read_line(before, true)?;
+
+                    if let Some(last) = line.last_mut() {
+                        last.variations.push(variation);
+                    }
+                }
+                Token::Close if variation => return Ok(line),
+                Token::Close => {
+                    return Err(Self::error_at(at, PgnErrorKind::UnbalancedVariation));
+                }
+                Token::Result(_) | Token::Tag(..) | Token::Eof if variation => {
+                    return Err(Self::error_at(at, PgnErrorKind::UnbalancedVariation));
+                }
+                Token::Result(result) => {
+                    self.result = result;
+                    return Ok(line);
+                }
+                // A new game without a result for the last one.
+                token => {
+                    self.peeked = Some((token, at));
+                    return Ok(line);
+                }
+            }
+        }
+    }
+
+    /// Skips the rest of a broken game: up to its result, or the tags of the next game.
+    fn skip_game(&mut self) {
+        loop {
+            match self.next_token() {
+                Ok((Token::Result(_), _)) | Ok((Token::Eof, _)) => return,
+                Ok((token @ Token::Tag(..), at)) => {
+                    self.peeked = Some((token, at));
+                    return;
+                }
+                Ok(_) => {}
+                // Nothing left to read.
+                Err(PgnError {
+                    kind: PgnErrorKind::Io(_) | PgnErrorKind::UnterminatedComment,
+                    ..
+                }) => return,
+                Err(_) => {}
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = Result<Game, PgnError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_game() {
+            Ok(game) => game.map(Ok),
+            Err(e) => {
+                self.skip_game();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod pgn_tests {
+    use super::*;
+
+    fn read(pgn: &str) -> Vec<Result<Game, PgnError>> {
+        PgnReader::new(pgn.as_bytes()).collect()
+    }
+
+    fn sans(board: &Board, moves: &[GameMove]) -> Vec<String> {
+        let mut board = board.clone();
+
+        moves
+            .iter()
+            .map(|mv| {
+                let san = board.to_san(mv.mv);
+                board.do_move(mv.mv).unwrap();
+                san
+            })
+            .collect()
+    }
+
+    #[test]
+    pub fn tags_and_movetext() {
+        let games = read(
+            "[Event \"Casual \\\"blitz\\\"\"]\n\
+             [Site \"?\"]\n\
+             [Result \"1-0\"]\n\
+             \n\
+             1. e4 e5 2.Nf3 Nc6 3. Bb5 a6 4. Ba4 Nf6 5. O-O 1-0\n",
+        );
+
+        assert_eq!(games.len(), 1);
+        let game = games[0].as_ref().unwrap();
+
+        assert_eq!(game.tag("Event"), Some("Casual \"blitz\""));
+        assert_eq!(game.tag("Site"), Some("?"));
+        assert_eq!(game.tag("White"), None);
+        assert_eq!(game.result, GameResult::WhiteWins);
+        assert_eq!(
+            sans(&game.start, &game.moves),
+            ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Ba4", "Nf6", "O-O"]
+        );
+
+        let boards = game.boards();
+        assert_eq!(boards.len(), 10);
+        assert_eq!(
+            boards[9].to_fen(),
+            "r1bqkb1r/1ppp1ppp/p1n2n2/4p3/B3P3/5N2/PPPP1PPP/RNBQ1RK1 b kq - 3 5"
+        );
+    }
+
+    #[test]
+    pub fn annotations() {
+        let games = read(
+            "{Before the game} 1. e4 {Best by test} $1 1... c5!? ; Sicilian\n\
+             2. Nf3 (2. c3 d5 (2... Nf6) 3. exd5) (2. d4?! cxd4) 2... d6 *",
+        );
+
+        let game = games[0].as_ref().unwrap();
+        let moves = &game.moves;
+
+        assert_eq!(game.result, GameResult::Unknown);
+        assert_eq!(
+            moves[0].starting_comment.as_deref(),
+            Some("Before the game")
+        );
+        assert_eq!(moves[0].comment.as_deref(), Some("Best by test"));
+        assert_eq!(moves[0].nags, [1]);
+        assert_eq!(moves[1].nags, [5]);
+        assert_eq!(moves[1].comment.as_deref(), Some("Sicilian"));
+
+        let after_c5 = &game.boards()[2];
+        let variations = &moves[2].variations;
+
+        assert_eq!(variations.len(), 2);
+        assert_eq!(sans(after_c5, &variations[0]), ["c3", "d5", "exd5"]);
+        assert_eq!(sans(after_c5, &variations[1]), ["d4", "cxd4"]);
+        assert_eq!(variations[1][0].nags, [6]);
+
+        let mut after_c3 = after_c5.clone();
+        after_c3.do_move(variations[0][0].mv).unwrap();
+        assert_eq!(sans(&after_c3, &variations[0][1].variations[0]), ["Nf6"]);
+    }
+
+    #[test]
+    pub fn multiple_games_and_quirks() {
+        let pgn = "\u{feff}% exported by some tool\r\n\
+                   [Event \"One\"]\r\n\
+                   [SetUp \"1\"]\r\n\
+                   [FEN \"4k3/8/8/8/8/8/8/R3K3 w Q - 0 1\"]\r\n\
+                   \r\n\
+                   1. O-O-O Kf7 1/2-1/2\r\n\
+                   [Event \"Two, no result\"]\n\
+                   1. d4\n\
+                   [Event \"Three\"]\n\
+                   1. e4 e5 0-1\n";
+
+        let games = read(pgn)
+            .into_iter()
+            .map(|game| game.unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(games.len(), 3);
+        assert_eq!(games[0].tag("Event"), Some("One"));
+        assert_eq!(games[0].result, GameResult::Draw);
+        assert_eq!(sans(&games[0].start, &games[0].moves), ["O-O-O", "Kf7"]);
+        assert_eq!(games[1].result, GameResult::Unknown);
+        assert_eq!(games[1].moves.len(), 1);
+        assert_eq!(games[2].result, GameResult::BlackWins);
+        assert_eq!(games[2].moves.len(), 2);
+    }
+
+    #[test]
+    pub fn errors_resume_with_next_game() {
+        let pgn = "[Event \"Bad\"]\n\
+                   \n\
+                   1. e4 e5 2. Ke3 Nc6 1-0\n\
+                   \n\
+                   [Event \"Good\"]\n\
+                   1. e4 *\n\
+                   [Event \"Unbalanced\"]\n\
+                   1. e4 (1. d4 *\n\
+                   [Event \"Comment\"]\n\
+                   1. e4 {never closed\n";
+
+        let games = read(pgn);
+
+        assert_eq!(games.len(), 4);
+        assert_eq!(
+            games[0],
+            Err(PgnError {
+                line: 3,
+                column: 13,
+                kind: PgnErrorKind::San(SanError::Illegal("Ke3".to_owned())),
+            })
+        );
+        assert_eq!(games[1].as_ref().unwrap().tag("Event"), Some("Good"));
+        assert_eq!(
+            games[2],
+            Err(PgnError {
+                line: 8,
+                column: 14,
+                kind: PgnErrorKind::UnbalancedVariation,
+            })
+        );
+        assert_eq!(
+            games[3],
+            Err(PgnError {
+                line: 10,
+                column: 7,
+                kind: PgnErrorKind::UnterminatedComment,
+            })
+        );
+        assert_eq!(
+            games[3].as_ref().unwrap_err().to_string(),
+            "invalid PGN at 10:7: unterminated comment"
+        );
+    }
+
+    #[test]
+    pub fn tag_errors() {
+        let games = read("[Event \"x]\n1. e4 *\n[FEN \"8/8 w - - 0 1\"]\n1. e4 *\n");
+
+        assert_eq!(games.len(), 2);
+        assert_eq!(
+            games[0].as_ref().unwrap_err().kind,
+            PgnErrorKind::UnterminatedTag
+        );
+        assert!(matches!(
+            games[1].as_ref().unwrap_err().kind,
+            PgnErrorKind::Fen(_)
+        ));
+    }
+
+    #[test]
+    pub fn write_annotated_game() {
+        let mut game = Game::new(Board::load_fen(STARTING_FEN.to_owned()).unwrap());
+        game.tags
+            .push(("White".to_owned(), "Engine \"dev\"".to_owned()));
+        game.tags
+            .push(("TimeControl".to_owned(), "60+1".to_owned()));
+        game.tags.push(("Event".to_owned(), "Test".to_owned()));
+        game.result = GameResult::Draw;
+
+        let mut board = game.start.clone();
+
+        for (i, san) in ["e4", "c5", "Nf3", "d6"].into_iter().enumerate() {
+            let mv = board.parse_san(san).unwrap();
+            board.do_move(mv).unwrap();
+
+            let mut game_move = GameMove::new(mv);
+            game_move.clock = Some(Duration::from_millis(60_000 - 1_500 * i as u64));
+            game.moves.push(game_move);
+        }
+
+        game.moves[1].eval = Some(Eval::Centipawns(35));
+        game.moves[1].comment = Some("Sicilian".to_owned());
+        game.moves[3].eval = Some(Eval::Mate(-12));
+        game.moves[3].nags.push(2);
+
+        let mut board = game.boards()[2].clone();
+        let pv = ["c3", "Nf6"].map(|san| {
+            let mv = board.parse_san(san).unwrap();
+            board.do_move(mv).unwrap();
+            mv
+        });
+        game.moves[2].variations.push(GameMove::line(pv));
+
+        assert_eq!(
+            game.to_pgn(),
+            "[Event \"Test\"]\n\
+             [Site \"?\"]\n\
+             [Date \"????.??.??\"]\n\
+             [Round \"?\"]\n\
+             [White \"Engine \\\"dev\\\"\"]\n\
+             [Black \"?\"]\n\
+             [Result \"1/2-1/2\"]\n\
+             [TimeControl \"60+1\"]\n\
+             \n\
+             1. e4 {[%clk 0:01:00]} 1... c5 {[%eval 0.35] [%clk 0:00:58.5] Sicilian} 2. Nf3\n\
+             {[%clk 0:00:57]} (2. c3 Nf6) 2... d6 $2 {[%eval #-12] [%clk 0:00:55.5]} 1/2-1/2\n"
+        );
+    }
+
+    #[test]
+    pub fn write_read_roundtrip() {
+        let pgn = "[Event \"Roundtrip\"]\n\
+                   [FEN \"r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1\"]\n\
+                   \n\
+                   {Start} 1. O-O {[%eval -0.5]} (1. O-O-O {[%clk 1:00:00] long} 1... O-O\n\
+                   (1... Rb8 $1)) 1... Ra7 $6 2. Rfb1 *";
+
+        let mut game = read(pgn).remove(0).unwrap();
+        assert_eq!(game.moves[0].eval, Some(Eval::Centipawns(-50)));
+        assert_eq!(game.moves[0].comment, None);
+        assert_eq!(
+            game.moves[0].variations[0][0].clock,
+            Some(Duration::from_secs(3600))
+        );
+
+        // `}` would end the comment early, so it is dropped.
+        game.moves[0].starting_comment = Some("Start }here".to_owned());
+        game.moves[1].comment = Some("a} b".to_owned());
+
+        let written = game.to_pgn();
+        let reread = read(&written).remove(0).unwrap();
+
+        game.moves[0].starting_comment = Some("Start here".to_owned());
+        game.moves[1].comment = Some("a b".to_owned());
+
+        assert_eq!(reread.start, game.start);
+        assert_eq!(reread.moves, game.moves);
+        assert_eq!(reread.result, game.result);
+        assert_eq!(reread.tag("FEN"), game.tag("FEN"));
+        assert_eq!(reread.to_pgn(), written);
+    }
+
+    #[test]
+    pub fn write_start_position() {
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1";
+        let mut game = Game::new(Board::load_fen(fen.to_owned()).unwrap());
+        let mv = game.start.parse_san("e4").unwrap();
+        game.moves.push(GameMove::new(mv));
+
+        let written = game.to_pgn();
+        assert!(written.contains(
+            "[Result \"*\"]\n[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/4P3/4K3 w - - 0 1\"]\n"
+        ));
+
+        let reread = read(&written).remove(0).unwrap();
+        assert_eq!(reread.start, game.start);
+        assert_eq!(reread.moves, game.moves);
+        assert_eq!(reread.tag("FEN"), Some(fen));
+
+        // Tags already there aren't written twice.
+        assert_eq!(reread.to_pgn(), written);
+
+        let standard = Game::new(Board::load_fen(STARTING_FEN.to_owned()).unwrap());
+        assert!(!standard.to_pgn().contains("FEN"));
+    }
+
+    #[test]
+    pub fn write_wraps_lines() {
+        let pgn = "1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4 Nf6 5. O-O Be7 6. Re1 b5 7. Bb3 d6 \
+                   8. c3 O-O 9. h3 Nb8 10. d4 Nbd7 11. c4 c6 12. cxb5 axb5 13. Nc3 Bb7 \
+                   14. Bg5 b4 15. Nb1 h6 16. Bh4 c5 17. dxe5 Nxe4 18. Bxe7 Qxe7 1-0";
+
+        let game = read(pgn).remove(0).unwrap();
+        let written = game.to_pgn();
+        let movetext = written.split("\n\n").nth(1).unwrap();
+
+        assert!(movetext.lines().count() > 1);
+        assert!(movetext.lines().all(|line| line.len() <= 80));
+        assert_eq!(read(&written).remove(0).unwrap().moves, game.moves);
+    }
+}
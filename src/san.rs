@@ -0,0 +1,337 @@
+//! Standard Algebraic Notation, as used in PGN and for anything shown to humans.
+
+use crate::{
+    board::Board,
+    piece::Piece,
+    r#move::{CastlingMethod, Move, MoveFlag},
+    square::*,
+};
+
+/// Why [Board::parse_san] couldn't turn a string into a move.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SanError {
+    /// Not SAN at all.
+    Syntax(String),
+    /// Well-formed, but no legal move matches.
+    Illegal(String),
+    /// More than one legal move matches.
+    Ambiguous(String),
+}
+
+impl core::fmt::Display for SanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Syntax(san) => write!(f, "invalid SAN '{}'", san),
+            Self::Illegal(san) => write!(f, "illegal move '{}'", san),
+            Self::Ambiguous(san) => write!(f, "ambiguous move '{}'", san),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+impl Board {
+    /// SAN of the legal move `mv`, e.g. `Nbd7`, `exd6`, `e8=Q+` or `O-O-O#`.
+    pub fn to_san(&self, mv: Move) -> String {
+        let mut san = match mv.flag {
+            MoveFlag::Castle(CastlingMethod::Short) => "O-O".to_owned(),
+            MoveFlag::Castle(CastlingMethod::Long) => "O-O-O".to_owned(),
+            _ => self.san_body(mv),
+        };
+
+        let mut after = self.clone();
+
+        if after.do_move(mv).is_ok() && after.in_check(after.turn) {
+            san.push(match after.generate_moves(after.turn).is_empty() {
+                true => '#',
+                false => '+',
+            });
+        }
+
+        san
+    }
+
+    /// Piece, disambiguation, capture, target square and promotion.
+    fn san_body(&self, mv: Move) -> String {
+        let (from, to) = (mv.starting_square, mv.target_square);
+        let piece = self.get_piece_type(from).unwrap_or(Piece::Pawn);
+
+        let capture = matches!(mv.flag, MoveFlag::Capture(_) | MoveFlag::EnPassant(_))
+            || self.squares[to as usize].is_some();
+
+        let mut san = String::with_capacity(8);
+
+        if piece == Piece::Pawn {
+            if capture {
+                san.push(file_char(from.file()));
+            }
+        } else {
+            san.push(piece.notation());
+
+            let rivals = self
+                .generate_moves(self.turn)
+                .into_iter()
+                .filter(|other| {
+                    other.target_square == to
+                        && other.starting_square != from
+                        && !matches!(other.flag, MoveFlag::Castle(_))
+                        && self.get_piece_type(other.starting_square) == Some(piece)
+                })
+                .map(|other| other.starting_square)
+                .collect::<Vec<_>>();
+
+            if !rivals.is_empty() {
+                let same_file = rivals.iter().any(|sq| sq.file() == from.file());
+                let same_rank = rivals.iter().any(|sq| sq.rank() == from.rank());
+
+                if !same_file || same_rank {
+                    san.push(file_char(from.file()));
+                }
+
+                if same_file {
+                    san.push(rank_char(from.rank()));
+                }
+            }
+        }
+
+        if capture {
+            san.push('x');
+        }
+
+        san.push(file_char(to.file()));
+        san.push(rank_char(to.rank()));
+
+        if let MoveFlag::Promotion(promotion) = mv.flag {
+            san.push('=');
+            san.push(promotion.notation());
+        }
+
+        san
+    }
+
+    /// Finds the legal move written as `san`. Besides strict SAN this accepts
+    /// castling with zeros, missing or superfluous check marks and capture
+    /// signs, annotations like `!?` and promotions without `=`.
+    pub fn parse_san(&self, san: &str) -> Result<Move, SanError> {
+        let syntax = || SanError::Syntax(san.to_owned());
+
+        let text = san.trim().trim_end_matches(['+', '#', '!', '?']);
+
+        let moves = self.generate_moves(self.turn);
+
+        let castling = match text {
+            "O-O" | "0-0" => Some(CastlingMethod::Short),
+            "O-O-O" | "0-0-0" => Some(CastlingMethod::Long),
+            _ => None,
+        };
+
+        if let Some(method) = castling {
+            return moves
+                .into_iter()
+                .find(|mv| mv.flag == MoveFlag::Castle(method))
+                .ok_or(SanError::Illegal(san.to_owned()));
+        }
+
+        let mut chars = text
+            .chars()
+            .filter(|c| !matches!(c, 'x' | ':' | '-'))
+            .collect::<Vec<_>>();
+
+        let piece = match chars.first() {
+            Some(c) if c.is_ascii_uppercase() => {
+                let piece = Piece::from_notation(*c).ok_or_else(syntax)?;
+                chars.remove(0);
+                piece
+            }
+            Some(_) => Piece::Pawn,
+            None => return Err(syntax()),
+        };
+
+        // `e8=Q`, `e8Q` and `e8q`, but not the b-file in `Qb4`.
+        let promotion = match chars.last() {
+            Some(c) if chars.len() > 2 && c.is_ascii_alphabetic() => {
+                let promotion = Piece::from_notation(c.to_ascii_uppercase()).ok_or_else(syntax)?;
+
+                chars.pop();
+
+                if chars.last() == Some(&'=') {
+                    chars.pop();
+                }
+
+                Some(promotion)
+            }
+            _ => None,
+        };
+
+        if chars.len() < 2 || chars.len() > 4 {
+            return Err(syntax());
+        }
+
+        let target = chars.split_off(chars.len() - 2);
+        let target = parse_square(target[0], target[1]).ok_or_else(syntax)?;
+
+        let (mut file, mut rank) = (None, None);
+
+        for c in chars {
+            match c {
+                'a'..='h' if file.is_none() => file = Some(c as usize - 'a' as usize),
+                '1'..='8' if rank.is_none() => rank = Some(c as usize - '1' as usize),
+                _ => return Err(syntax()),
+            }
+        }
+
+        let mut candidates = moves.into_iter().filter(|mv| {
+            let from = mv.starting_square;
+
+            mv.target_square == target
+                && !matches!(mv.flag, MoveFlag::Castle(_))
+                && self.get_piece_type(from) == Some(piece)
+                && file.is_none_or(|file| from.file() as usize == file)
+                && rank.is_none_or(|rank| from.rank() as usize == rank)
+                && match mv.flag {
+                    MoveFlag::Promotion(p) => promotion == Some(p),
+                    _ => promotion.is_none(),
+                }
+        });
+
+        match (candidates.next(), candidates.next()) {
+            (Some(mv), None) => Ok(mv),
+            (None, _) => Err(SanError::Illegal(san.to_owned())),
+            (Some(_), Some(_)) => Err(SanError::Ambiguous(san.to_owned())),
+        }
+    }
+}
+
+fn file_char(file: File) -> char {
+    (b'a' + file as u8) as char
+}
+
+fn rank_char(rank: Rank) -> char {
+    (b'1' + rank as u8) as char
+}
+
+fn parse_square(file: char, rank: char) -> Option<Square> {
+    if !('a'..='h').contains(&file) || !('1'..='8').contains(&rank) {
+        return None;
+    }
+
+    Some(Square::new(
+        File::index(file as usize - 'a' as usize),
+        Rank::index(rank as usize - '1' as usize),
+    ))
+}
+
+#[cfg(test)]
+mod san_tests {
+    use super::*;
+
+    fn board(fen: &str) -> Board {
+        Board::load_fen(fen.to_owned()).unwrap()
+    }
+
+    const STARTPOS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    #[test]
+    pub fn roundtrip_all_moves() {
+        for fen in [
+            STARTPOS,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "4k3/1P6/8/3pP3/8/8/8/4K3 w - d6 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        ] {
+            let board = board(fen);
+
+            for mv in board.generate_moves(board.turn) {
+                let san = board.to_san(mv);
+                assert_eq!(board.parse_san(&san), Ok(mv), "{} in {}", san, fen);
+            }
+        }
+    }
+
+    #[test]
+    pub fn formatting() {
+        let board = board("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let san = |s| board.to_san(board.parse_san(s).unwrap());
+
+        assert_eq!(san("O-O-O"), "O-O-O");
+        assert_eq!(san("dxe6"), "dxe6");
+        assert_eq!(san("Nxf7"), "Nxf7");
+        assert_eq!(san("Qxf6"), "Qxf6");
+        assert_eq!(san("Bxa6"), "Bxa6");
+
+        let cases = [
+            ("4k3/8/8/8/8/8/4K3/R6R w - - 0 1", "Rad1", "Rad1"),
+            ("4k3/8/8/8/8/8/4K3/R6R w - - 0 1", "Rhf1", "Rhf1"),
+            ("4k3/8/8/8/8/8/8/R3K2R w - - 0 1", "Rhf1", "Rf1"),
+            ("k7/8/8/N7/8/8/8/N3K3 w - - 0 1", "N1b3", "N1b3"),
+            ("7k/8/Q1Q5/8/Q7/8/8/4K3 w - - 0 1", "Qa6b5", "Qa6b5"),
+            ("7k/8/Q1Q5/8/Q7/8/8/4K3 w - - 0 1", "Qab7", "Qab7"),
+            ("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "Ra8", "Ra8#"),
+            ("4k3/1P6/8/3pP3/8/8/8/4K3 w - d6 0 1", "exd6", "exd6"),
+            ("4k3/1P6/8/3pP3/8/8/8/4K3 w - d6 0 1", "b8=Q", "b8=Q+"),
+        ];
+
+        for (fen, input, expected) in cases {
+            let board = self::board(fen);
+            assert_eq!(board.to_san(board.parse_san(input).unwrap()), expected);
+        }
+    }
+
+    #[test]
+    pub fn lenient_parsing() {
+        let board = board("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1");
+
+        assert_eq!(board.parse_san("0-0"), board.parse_san("O-O"));
+        assert_eq!(board.parse_san("0-0-0"), board.parse_san("O-O-O"));
+        assert_eq!(board.parse_san("Rxa8"), board.parse_san("Rxa8+"));
+        assert_eq!(board.parse_san("Ra8+!?"), board.parse_san("Rxa8+"));
+
+        let board = self::board("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1");
+        let queen = board.parse_san("b8=Q+");
+
+        assert!(queen.is_ok());
+        assert_eq!(board.parse_san("b8Q"), queen);
+        assert_eq!(board.parse_san("b8q"), queen);
+        assert_eq!(board.parse_san("b8=q"), queen);
+    }
+
+    #[test]
+    pub fn errors() {
+        let board = board(STARTPOS);
+
+        assert_eq!(board.parse_san(""), Err(SanError::Syntax("".to_owned())));
+        assert_eq!(
+            board.parse_san("Xe4"),
+            Err(SanError::Syntax("Xe4".to_owned()))
+        );
+        assert_eq!(
+            board.parse_san("e9"),
+            Err(SanError::Syntax("e9".to_owned()))
+        );
+        assert_eq!(
+            board.parse_san("e5"),
+            Err(SanError::Illegal("e5".to_owned()))
+        );
+        assert_eq!(
+            board.parse_san("O-O"),
+            Err(SanError::Illegal("O-O".to_owned()))
+        );
+
+        let board = self::board("4k3/8/8/8/8/8/4K3/R6R w - - 0 1");
+        assert_eq!(
+            board.parse_san("Rd1"),
+            Err(SanError::Ambiguous("Rd1".to_owned()))
+        );
+
+        // Promotions need a piece, other moves must not have one.
+        let board = self::board("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(
+            board.parse_san("b8"),
+            Err(SanError::Illegal("b8".to_owned()))
+        );
+        assert_eq!(
+            board.parse_san("Kd1=Q"),
+            Err(SanError::Illegal("Kd1=Q".to_owned()))
+        );
+    }
+}
@@ -0,0 +1,1263 @@
+//! Iterative deepening alpha-beta search with quiescence, a transposition
+//! table and the usual move ordering (hash move, MVV-LVA, killers, history).
+//!
+//! The search runs on the calling thread and is steered through a shared
+//! [SearchControl]: the UCI side sets its stop flag, or turns a ponder search
+//! into a timed one with [SearchControl::ponderhit].
+
+use std::{
+    cmp::Reverse,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    board::Board,
+    eval::{self, piece_value},
+    piece::{Color, Piece},
+    r#move::{Move, MoveFlag, PackedMove},
+};
+
+/// Deepest ply the search reaches, quiescence included.
+pub const MAX_PLY: usize = 128;
+
+/// Deepest iteration started without a depth limit.
+const MAX_DEPTH: u32 = 100;
+
+/// Score for mating at the root, mates further away score a ply less each.
+pub const MATE: i32 = 32000;
+
+const INFINITY: i32 = MATE + 1;
+
+/// Scores beyond this are mates.
+const MATE_BOUND: i32 = MATE - MAX_PLY as i32;
+
+/// Nodes between checks of the clock and the stop flag.
+const POLL_INTERVAL: u64 = 1024;
+
+/// A score as reported over UCI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Score {
+    /// Centipawns from the side to move's point of view.
+    Cp(i32),
+    /// Moves (not plies) to mate, negative if the side to move gets mated.
+    Mate(i32),
+}
+
+impl Score {
+    fn from_search(score: i32) -> Self {
+        if score >= MATE_BOUND {
+            Self::Mate((MATE - score + 1) / 2)
+        } else if score <= -MATE_BOUND {
+            Self::Mate(-(MATE + score) / 2)
+        } else {
+            Self::Cp(score)
+        }
+    }
+
+    /// Win, draw and loss chances in permille, as in `info wdl`. A logistic
+    /// model where +100 cp is an even chance of winning.
+    pub fn wdl(self) -> (u32, u32, u32) {
+        const EVEN: f64 = 100.0;
+        const SCALE: f64 = 40.0;
+
+        match self {
+            Self::Mate(moves) if moves > 0 => (1000, 0, 0),
+            Self::Mate(_) => (0, 0, 1000),
+            Self::Cp(cp) => {
+                let chance =
+                    |cp: f64| (1000.0 / (1.0 + ((EVEN - cp) / SCALE).exp())).round() as u32;
+                let (win, loss) = (chance(cp as f64), chance(-cp as f64));
+
+                (win, 1000 - win - loss, loss)
+            }
+        }
+    }
+}
+
+/// As in `info score`, e.g. `cp 35` or `mate -2`.
+impl core::fmt::Display for Score {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cp(cp) => write!(f, "cp {}", cp),
+            Self::Mate(moves) => write!(f, "mate {}", moves),
+        }
+    }
+}
+
+/// What to stop the search at, from `go`. Without any limit it runs until stopped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    /// Stop once a mate in this many moves is found.
+    pub mate: Option<u32>,
+    pub movetime: Option<Duration>,
+    /// Time left on the clock of the side to move.
+    pub time: Option<Duration>,
+    /// Increment of the side to move.
+    pub increment: Duration,
+    pub moves_to_go: Option<u32>,
+    /// Kept off the clock for communication delays.
+    pub move_overhead: Duration,
+    pub infinite: bool,
+    /// Root moves to choose from, all of them if empty.
+    pub searchmoves: Vec<Move>,
+}
+
+impl Limits {
+    /// Time after which no new iteration is started, and time after which
+    /// the search is aborted, `None` without a time limit.
+    pub fn time_budget(&self) -> Option<(Duration, Duration)> {
+        if let Some(movetime) = self.movetime {
+            let maximum = movetime.saturating_sub(self.move_overhead);
+            return Some((maximum, maximum));
+        }
+
+        let available = self.time?.saturating_sub(self.move_overhead);
+        let moves_to_go = self.moves_to_go.unwrap_or(30).max(1);
+
+        let target = (available / moves_to_go + self.increment * 3 / 4).min(available);
+
+        // The next iteration usually takes longer than all the ones before it.
+        Some((target / 2, (target * 2).min(available)))
+    }
+}
+
+/// Shared between the search and whoever started it.
+#[derive(Debug, Default)]
+pub struct SearchControl {
+    stop: AtomicBool,
+    pondering: AtomicBool,
+}
+
+impl SearchControl {
+    /// Control for a search started with `go`, or `go ponder`.
+    pub fn new(ponder: bool) -> Self {
+        Self {
+            stop: AtomicBool::new(false),
+            pondering: AtomicBool::new(ponder),
+        }
+    }
+
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+
+    /// The opponent played the expected move: the clock now applies, counted
+    /// from the start of the ponder search.
+    pub fn ponderhit(&self) {
+        self.pondering.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_pondering(&self) -> bool {
+        self.pondering.load(Ordering::Relaxed)
+    }
+}
+
+/// Progress after a completed iteration, sent as `info`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchInfo {
+    pub depth: u32,
+    /// Rank of this line among the root moves, 1 for the best.
+    pub multipv: usize,
+    pub seldepth: u32,
+    pub score: Score,
+    pub nodes: u64,
+    pub time: Duration,
+    /// Permille of the transposition table in use.
+    pub hashfull: u32,
+    pub pv: Vec<Move>,
+    /// [Score::wdl], if the GUI asked for it.
+    pub wdl: Option<(u32, u32, u32)>,
+}
+
+impl SearchInfo {
+    /// Nodes per second.
+    pub fn nps(&self) -> u64 {
+        match self.time.as_micros() {
+            0 => self.nodes,
+            micros => (self.nodes as u128 * 1_000_000 / micros) as u64,
+        }
+    }
+
+    /// The `info` line without the leading `info`, with moves written for
+    /// `board`, the position searched.
+    pub fn to_uci(&self, board: &Board) -> String {
+        let mut line = format!(
+            "depth {} seldepth {} multipv {} score {}",
+            self.depth, self.seldepth, self.multipv, self.score
+        );
+
+        if let Some((win, draw, loss)) = self.wdl {
+            line.push_str(&format!(" wdl {} {} {}", win, draw, loss));
+        }
+
+        line.push_str(&format!(
+            " nodes {} nps {} hashfull {} time {} pv",
+            self.nodes,
+            self.nps(),
+            self.hashfull,
+            self.time.as_millis()
+        ));
+
+        for mv in &self.pv {
+            line.push_str(&format!(" {}", board.board_to_uci_move(*mv)));
+        }
+
+        line
+    }
+}
+
+/// Outcome of [Searcher::search].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchResult {
+    /// `None` if there are no legal moves.
+    pub best: Option<Move>,
+    /// The expected reply to `best`, to ponder on.
+    pub ponder: Option<Move>,
+    pub score: Score,
+    /// Last completed iteration.
+    pub depth: u32,
+    pub nodes: u64,
+    pub stats: SearchStats,
+}
+
+/// How well the transposition table and move ordering did, for debugging.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    pub tt_probes: u64,
+    pub tt_hits: u64,
+    /// Hits that ended the node without searching it.
+    pub tt_cutoffs: u64,
+    pub beta_cutoffs: u64,
+    /// Beta cutoffs caused by the first move searched.
+    pub first_move_cutoffs: u64,
+    pub hashfull: u32,
+}
+
+impl core::fmt::Display for SearchStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let percent = |part: u64, total: u64| match total {
+            0 => 0.0,
+            total => part as f64 * 100.0 / total as f64,
+        };
+
+        write!(
+            f,
+            "tt probes {} hits {} ({:.1}%) cutoffs {} hashfull {}",
+            self.tt_probes,
+            self.tt_hits,
+            percent(self.tt_hits, self.tt_probes),
+            self.tt_cutoffs,
+            self.hashfull,
+        )?;
+
+        write!(
+            f,
+            " ordering cutoffs {} first move {} ({:.1}%)",
+            self.beta_cutoffs,
+            self.first_move_cutoffs,
+            percent(self.first_move_cutoffs, self.beta_cutoffs),
+        )
+    }
+}
+
+/// Search state kept between searches: the transposition table and the
+/// move ordering statistics.
+pub struct Searcher {
+    tt: TranspositionTable,
+    killers: [[PackedMove; 2]; MAX_PLY],
+    /// Indexed by color, starting and target square.
+    history: Box<[[[i32; 64]; 64]; 2]>,
+    /// Centipawns a draw is worth less than zero to the side to move at the root.
+    pub contempt: i32,
+    /// Lines reported per iteration, each with a different root move.
+    pub multipv: usize,
+    /// Report win, draw and loss chances with the score (UCI_ShowWDL).
+    pub show_wdl: bool,
+}
+
+impl Searcher {
+    pub fn new(hash_mb: usize) -> Self {
+        Self {
+            tt: TranspositionTable::new(hash_mb),
+            killers: [[PackedMove::NONE; 2]; MAX_PLY],
+            history: Box::new([[[0; 64]; 64]; 2]),
+            contempt: 0,
+            multipv: 1,
+            show_wdl: false,
+        }
+    }
+
+    pub fn resize(&mut self, hash_mb: usize) {
+        self.tt = TranspositionTable::new(hash_mb);
+    }
+
+    /// Forgets everything learned, for a new game.
+    pub fn clear(&mut self) {
+        self.tt.clear();
+        self.killers = [[PackedMove::NONE; 2]; MAX_PLY];
+        *self.history = [[[0; 64]; 64]; 2];
+    }
+
+    /// Searches `board` until `limits` or `control` stop it, calling `report`
+    /// after every completed iteration. `history` holds the Zobrist keys of
+    /// the game's earlier positions, for repetitions.
+    pub fn search(
+        &mut self,
+        board: &Board,
+        history: &[u64],
+        limits: &Limits,
+        control: &SearchControl,
+        mut report: impl FnMut(&SearchInfo),
+    ) -> SearchResult {
+        self.killers = [[PackedMove::NONE; 2]; MAX_PLY];
+
+        for from in self.history.iter_mut().flatten() {
+            from.iter_mut().for_each(|score| *score /= 2);
+        }
+
+        let mut search = Search {
+            start: Instant::now(),
+            budget: limits.time_budget(),
+            searcher: self,
+            limits,
+            control,
+            root_color: board.turn,
+            nodes: 0,
+            seldepth: 0,
+            aborted: false,
+            keys: history.to_vec(),
+            pv: vec![vec![]; MAX_PLY + 1],
+            excluded: vec![],
+            stats: SearchStats::default(),
+        };
+
+        search.iterate(&mut board.clone(), &mut report)
+    }
+}
+
+/// Bound of a transposition table score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Bound {
+    Exact,
+    /// Failed high, the score is at least this.
+    Lower,
+    /// Failed low, the score is at most this.
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TtEntry {
+    key: u64,
+    mv: PackedMove,
+    score: i16,
+    depth: u8,
+    bound: Bound,
+}
+
+impl TtEntry {
+    const EMPTY: Self = Self {
+        key: 0,
+        mv: PackedMove::NONE,
+        score: 0,
+        depth: 0,
+        bound: Bound::Exact,
+    };
+}
+
+/// Always replacing, one entry per slot.
+struct TranspositionTable {
+    entries: Vec<TtEntry>,
+}
+
+impl TranspositionTable {
+    fn new(hash_mb: usize) -> Self {
+        let len = (hash_mb << 20) / std::mem::size_of::<TtEntry>();
+
+        // A power of two, so indexing is a mask.
+        let len = match len {
+            0 => 1,
+            len => 1 << len.ilog2(),
+        };
+
+        Self {
+            entries: vec![TtEntry::EMPTY; len],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.entries.fill(TtEntry::EMPTY);
+    }
+
+    fn index(&self, key: u64) -> usize {
+        key as usize & (self.entries.len() - 1)
+    }
+
+    fn probe(&self, key: u64) -> Option<TtEntry> {
+        let entry = self.entries[self.index(key)];
+
+        (entry.key == key).then_some(entry)
+    }
+
+    fn store(&mut self, key: u64, mv: PackedMove, score: i32, depth: i32, bound: Bound) {
+        let index = self.index(key);
+
+        self.entries[index] = TtEntry {
+            key,
+            mv,
+            score: score as i16,
+            depth: depth.clamp(0, u8::MAX as i32) as u8,
+            bound,
+        };
+    }
+
+    /// Permille of the entries in use, sampled from the first thousand.
+    fn hashfull(&self) -> u32 {
+        let sample = &self.entries[..self.entries.len().min(1000)];
+        let used = sample.iter().filter(|entry| entry.key != 0).count();
+
+        (used * 1000 / sample.len()) as u32
+    }
+}
+
+/// Mate scores are stored relative to the node, not the root.
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    match score {
+        s if s >= MATE_BOUND => s + ply as i32,
+        s if s <= -MATE_BOUND => s - ply as i32,
+        s => s,
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    match score {
+        s if s >= MATE_BOUND => s - ply as i32,
+        s if s <= -MATE_BOUND => s + ply as i32,
+        s => s,
+    }
+}
+
+/// Piece taken by `mv`, if any.
+fn captured(board: &Board, mv: Move) -> Option<Piece> {
+    match mv.flag {
+        MoveFlag::EnPassant(_) => Some(Piece::Pawn),
+        // Promotions can capture too.
+        _ => board.squares[mv.target_square as usize].map(|(_, piece)| piece),
+    }
+}
+
+fn is_tactical(board: &Board, mv: Move) -> bool {
+    matches!(mv.flag, MoveFlag::Promotion(_)) || captured(board, mv).is_some()
+}
+
+/// State of one search.
+struct Search<'a> {
+    searcher: &'a mut Searcher,
+    limits: &'a Limits,
+    control: &'a SearchControl,
+    start: Instant,
+    budget: Option<(Duration, Duration)>,
+    root_color: Color,
+    nodes: u64,
+    seldepth: usize,
+    aborted: bool,
+    /// Zobrist keys of the game and the current line, up to the parent node.
+    keys: Vec<u64>,
+    /// Principal variation found below every ply.
+    pv: Vec<Vec<Move>>,
+    /// Root moves of the lines already found in this iteration.
+    excluded: Vec<Move>,
+    stats: SearchStats,
+}
+
+impl Search<'_> {
+    fn iterate(&mut self, board: &mut Board, report: &mut impl FnMut(&SearchInfo)) -> SearchResult {
+        let moves = board.generate_moves(board.turn);
+
+        let root_moves = moves
+            .iter()
+            .copied()
+            .filter(|mv| self.searches_root_move(mv))
+            .collect::<Vec<_>>();
+
+        let mut result = SearchResult {
+            best: root_moves.first().copied(),
+            ponder: None,
+            score: Score::Cp(0),
+            depth: 0,
+            nodes: 0,
+            stats: SearchStats::default(),
+        };
+
+        if moves.is_empty() && board.in_check(board.turn) {
+            result.score = Score::Mate(0);
+        }
+
+        if root_moves.is_empty() {
+            return result;
+        }
+
+        let max_depth = self.limits.depth.unwrap_or(MAX_DEPTH).clamp(1, MAX_DEPTH);
+        let line_count = self.searcher.multipv.clamp(1, root_moves.len());
+
+        for depth in 1..=max_depth {
+            self.seldepth = 0;
+            self.excluded.clear();
+
+            // Every line is the best of the root moves the ones before it left.
+            let mut lines = vec![];
+
+            while lines.len() < line_count {
+                let score = self.negamax(board, depth as i32, 0, -INFINITY, INFINITY);
+
+                if self.aborted {
+                    break;
+                }
+
+                self.excluded.push(self.pv[0][0]);
+                lines.push((score, self.pv[0].clone()));
+            }
+
+            if self.aborted {
+                // Root moves only replace the best one after a complete search,
+                // so an interrupted first line's choice is still sound.
+                let pv = lines.first().map_or(&self.pv[0], |(_, pv)| pv);
+
+                if let Some(&best) = pv.first() {
+                    result.best = Some(best);
+                    result.ponder = pv.get(1).copied();
+                }
+
+                break;
+            }
+
+            // A later line can come out ahead, with the hash table's help.
+            lines.sort_by_key(|(score, _)| Reverse(*score));
+
+            result.best = Some(lines[0].1[0]);
+            result.ponder = lines[0].1.get(1).copied();
+            result.score = Score::from_search(lines[0].0);
+            result.depth = depth;
+
+            for (i, (score, pv)) in lines.into_iter().enumerate() {
+                report(&SearchInfo {
+                    depth,
+                    multipv: i + 1,
+                    seldepth: self.seldepth as u32,
+                    score: Score::from_search(score),
+                    nodes: self.nodes,
+                    time: self.start.elapsed(),
+                    hashfull: self.searcher.tt.hashfull(),
+                    pv,
+                    wdl: self
+                        .searcher
+                        .show_wdl
+                        .then(|| Score::from_search(score).wdl()),
+                });
+            }
+
+            let mate_found = match (result.score, self.limits.mate) {
+                (Score::Mate(moves), Some(limit)) => moves > 0 && moves as u32 <= limit,
+                _ => false,
+            };
+
+            if mate_found
+                || self
+                    .budget
+                    .is_some_and(|(optimum, _)| self.out_of_time(optimum))
+            {
+                break;
+            }
+        }
+
+        result.nodes = self.nodes;
+        result.stats = SearchStats {
+            hashfull: self.searcher.tt.hashfull(),
+            ..self.stats
+        };
+
+        if let (Some(best), None) = (result.best, result.ponder) {
+            result.ponder = self.ponder_move(board, best);
+        }
+
+        result
+    }
+
+    /// Whether the root move `mv` is searched: it's one of the `searchmoves`,
+    /// if there are any, and not the move of a line already found.
+    fn searches_root_move(&self, mv: &Move) -> bool {
+        (self.limits.searchmoves.is_empty() || self.limits.searchmoves.contains(mv))
+            && !self.excluded.contains(mv)
+    }
+
+    /// Whether `limit` has passed. The clock doesn't run while pondering.
+    fn out_of_time(&self, limit: Duration) -> bool {
+        !self.limits.infinite && !self.control.is_pondering() && self.start.elapsed() >= limit
+    }
+
+    fn should_abort(&mut self) -> bool {
+        if self.aborted {
+            return true;
+        }
+
+        if self.limits.nodes.is_some_and(|nodes| self.nodes >= nodes) {
+            self.aborted = true;
+        } else if self.nodes.is_multiple_of(POLL_INTERVAL) {
+            self.aborted = self.control.is_stopped()
+                || self
+                    .budget
+                    .is_some_and(|(_, maximum)| self.out_of_time(maximum));
+        }
+
+        self.aborted
+    }
+
+    /// Draws are worth `contempt` less to the side to move at the root.
+    fn draw_score(&self, turn: Color) -> i32 {
+        match turn == self.root_color {
+            true => -self.searcher.contempt,
+            false => self.searcher.contempt,
+        }
+    }
+
+    /// Fifty-move rule, or a repetition of a position since the last capture
+    /// or pawn move.
+    fn is_draw(&self, board: &Board, key: u64) -> bool {
+        let n = self.keys.len();
+
+        board.halfmove_count >= 100
+            || (2..=board.halfmove_count.min(n))
+                .step_by(2)
+                .any(|back| self.keys[n - back] == key)
+    }
+
+    fn update_pv(&mut self, ply: usize, mv: Move) {
+        let (line, below) = self.pv.split_at_mut(ply + 1);
+
+        line[ply].clear();
+        line[ply].push(mv);
+        line[ply].extend_from_slice(&below[0]);
+    }
+
+    /// Moves paired with their ordering scores, best first once picked.
+    fn order(
+        &self,
+        board: &Board,
+        moves: Vec<Move>,
+        tt_move: PackedMove,
+        ply: usize,
+    ) -> Vec<(Move, i32)> {
+        let killers = self.searcher.killers[ply];
+
+        moves
+            .into_iter()
+            .map(|mv| {
+                let packed = PackedMove::from(mv);
+                let attacker = board.squares[mv.starting_square as usize]
+                    .map_or(Piece::Pawn, |(_, piece)| piece);
+
+                let score = if packed == tt_move {
+                    3_000_000
+                } else if let Some(victim) = captured(board, mv) {
+                    2_000_000 + 10 * piece_value(victim) - piece_value(attacker)
+                } else if let MoveFlag::Promotion(piece) = mv.flag {
+                    1_900_000 + piece_value(piece)
+                } else if packed == killers[0] {
+                    1_800_000
+                } else if packed == killers[1] {
+                    1_700_000
+                } else {
+                    self.searcher.history[board.turn as usize][mv.starting_square as usize]
+                        [mv.target_square as usize]
+                };
+
+                (mv, score)
+            })
+            .collect()
+    }
+
+    /// Moves the best scored of `moves[i..]` to `i` and returns it.
+    fn pick(moves: &mut [(Move, i32)], i: usize) -> Move {
+        let best = (i..moves.len())
+            .max_by_key(|&j| moves[j].1)
+            .expect("picked past the end");
+
+        moves.swap(i, best);
+        moves[i].0
+    }
+
+    /// Remembers a quiet move that caused a beta cutoff.
+    fn reward_quiet(&mut self, board: &Board, mv: Move, depth: i32, ply: usize) {
+        let packed = PackedMove::from(mv);
+        let killers = &mut self.searcher.killers[ply];
+
+        if killers[0] != packed {
+            killers[1] = killers[0];
+            killers[0] = packed;
+        }
+
+        let history = &mut self.searcher.history[board.turn as usize][mv.starting_square as usize]
+            [mv.target_square as usize];
+
+        // Stays well below the killers' ordering scores.
+        *history = (*history + depth * depth).min(1_000_000);
+    }
+
+    fn negamax(
+        &mut self,
+        board: &mut Board,
+        mut depth: i32,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        self.pv[ply].clear();
+
+        if self.should_abort() {
+            return 0;
+        }
+
+        let key = board.zobrist();
+
+        if ply > 0 && self.is_draw(board, key) {
+            return self.draw_score(board.turn);
+        }
+
+        if ply >= MAX_PLY - 1 {
+            return eval::evaluate(board);
+        }
+
+        let in_check = board.in_check(board.turn);
+
+        if in_check {
+            depth += 1;
+        }
+
+        if depth <= 0 {
+            return self.quiescence(board, ply, alpha, beta);
+        }
+
+        self.nodes += 1;
+        self.seldepth = self.seldepth.max(ply);
+
+        let tt_entry = self.searcher.tt.probe(key);
+        let tt_move = tt_entry.map_or(PackedMove::NONE, |entry| entry.mv);
+
+        self.stats.tt_probes += 1;
+        self.stats.tt_hits += tt_entry.is_some() as u64;
+
+        if let Some(entry) = tt_entry.filter(|entry| ply > 0 && entry.depth as i32 >= depth) {
+            let score = score_from_tt(entry.score as i32, ply);
+
+            let cutoff = match entry.bound {
+                Bound::Exact => true,
+                Bound::Lower => score >= beta,
+                Bound::Upper => score <= alpha,
+            };
+
+            if cutoff {
+                self.stats.tt_cutoffs += 1;
+                return score;
+            }
+        }
+
+        let moves = board.generate_moves(board.turn);
+
+        if moves.is_empty() {
+            return match in_check {
+                true => -MATE + ply as i32,
+                false => self.draw_score(board.turn),
+            };
+        }
+
+        let moves = match ply {
+            0 => moves
+                .into_iter()
+                .filter(|mv| self.searches_root_move(mv))
+                .collect(),
+            _ => moves,
+        };
+
+        let mut moves = self.order(board, moves, tt_move, ply);
+        let original_alpha = alpha;
+        let mut best = (-INFINITY, None);
+
+        self.keys.push(key);
+
+        for i in 0..moves.len() {
+            let mv = Self::pick(&mut moves, i);
+
+            board.do_move(mv).expect("generated move failed");
+            let score = -self.negamax(board, depth - 1, ply + 1, -beta, -alpha);
+            board.undo_move(mv).expect("failed to undo generated move");
+
+            if self.aborted {
+                self.keys.pop();
+                return 0;
+            }
+
+            if score > best.0 {
+                best = (score, Some(mv));
+
+                if score > alpha {
+                    alpha = score;
+                    self.update_pv(ply, mv);
+
+                    if score >= beta {
+                        self.stats.beta_cutoffs += 1;
+                        self.stats.first_move_cutoffs += (i == 0) as u64;
+
+                        if !is_tactical(board, mv) {
+                            self.reward_quiet(board, mv, depth, ply);
+                        }
+
+                        break;
+                    }
+                }
+            }
+        }
+
+        self.keys.pop();
+
+        let bound = if best.0 >= beta {
+            Bound::Lower
+        } else if best.0 > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+
+        // Without all its moves the root's score isn't the position's.
+        if ply > 0 || (self.excluded.is_empty() && self.limits.searchmoves.is_empty()) {
+            let packed = best.1.map_or(PackedMove::NONE, PackedMove::from);
+            self.searcher
+                .tt
+                .store(key, packed, score_to_tt(best.0, ply), depth, bound);
+        }
+
+        best.0
+    }
+
+    /// Captures and promotions only, or all evasions when in check, until the
+    /// position is quiet.
+    fn quiescence(&mut self, board: &mut Board, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        self.pv[ply].clear();
+
+        if self.should_abort() {
+            return 0;
+        }
+
+        self.nodes += 1;
+        self.seldepth = self.seldepth.max(ply);
+
+        if ply >= MAX_PLY - 1 {
+            return eval::evaluate(board);
+        }
+
+        let in_check = board.in_check(board.turn);
+        let mut best = -INFINITY;
+
+        // Standing pat: the side to move doesn't have to capture.
+        if !in_check {
+            best = eval::evaluate(board);
+
+            if best >= beta {
+                return best;
+            }
+
+            alpha = alpha.max(best);
+        }
+
+        let moves = board.generate_moves(board.turn);
+
+        if in_check && moves.is_empty() {
+            return -MATE + ply as i32;
+        }
+
+        let moves = moves
+            .into_iter()
+            .filter(|&mv| in_check || is_tactical(board, mv))
+            .collect();
+
+        let mut moves = self.order(board, moves, PackedMove::NONE, ply);
+
+        for i in 0..moves.len() {
+            let mv = Self::pick(&mut moves, i);
+
+            board.do_move(mv).expect("generated move failed");
+            let score = -self.quiescence(board, ply + 1, -beta, -alpha);
+            board.undo_move(mv).expect("failed to undo generated move");
+
+            if self.aborted {
+                return 0;
+            }
+
+            if score > best {
+                best = score;
+
+                if score > alpha {
+                    alpha = score;
+                    self.update_pv(ply, mv);
+
+                    if score >= beta {
+                        break;
+                    }
+                }
+            }
+        }
+
+        best
+    }
+
+    /// The reply to `best` the transposition table expects, for when the PV
+    /// was cut short.
+    fn ponder_move(&self, board: &mut Board, best: Move) -> Option<Move> {
+        board.do_move(best).ok()?;
+
+        let reply = self
+            .searcher
+            .tt
+            .probe(board.zobrist())
+            .and_then(|entry| board.unpack_move(entry.mv))
+            .filter(|&mv| !PackedMove::from(mv).is_none() && board.is_legal(mv));
+
+        board.undo_move(best).ok()?;
+
+        reply
+    }
+}
+
+#[cfg(test)]
+mod search_tests {
+    use super::*;
+    use crate::uci::UciMove;
+
+    fn search(fen: &str, limits: Limits) -> (Board, SearchResult) {
+        let board = Board::load_fen(fen.to_owned()).unwrap();
+        let result =
+            Searcher::new(1).search(&board, &[], &limits, &SearchControl::default(), |_| {});
+
+        (board, result)
+    }
+
+    fn uci(board: &Board, mv: Option<Move>) -> String {
+        board.board_to_uci_move(mv.unwrap()).to_string()
+    }
+
+    fn depth(depth: u32) -> Limits {
+        Limits {
+            depth: Some(depth),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    pub fn finds_mates() {
+        // Back rank mate.
+        let (board, result) = search("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", depth(3));
+        assert_eq!(uci(&board, result.best), "a1a8");
+        assert_eq!(result.score, Score::Mate(1));
+
+        // Mate in two with a quiet first move: 1. Kf7 Kh7 2. Rh1#.
+        let (board, result) = search("7k/8/5K2/8/8/8/8/3R4 w - - 0 1", depth(4));
+        assert_eq!(result.score, Score::Mate(2));
+        assert!(board.is_legal(result.best.unwrap()));
+    }
+
+    #[test]
+    pub fn wins_material() {
+        // The queen hangs.
+        let (board, result) = search("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", depth(3));
+        assert_eq!(uci(&board, result.best), "d2d5");
+        assert!(matches!(result.score, Score::Cp(cp) if cp > 400));
+    }
+
+    #[test]
+    pub fn no_moves() {
+        // Stalemate.
+        let (_, result) = search("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", depth(3));
+        assert_eq!(result.best, None);
+        assert_eq!(result.score, Score::Cp(0));
+
+        // Checkmate.
+        let (_, result) = search("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1", depth(3));
+        assert_eq!(result.best, None);
+        assert_eq!(result.score, Score::Mate(0));
+    }
+
+    #[test]
+    pub fn ponder_move() {
+        let (board, result) = search(
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            depth(4),
+        );
+
+        let mut after = board.clone();
+        after.do_move(result.best.unwrap()).unwrap();
+
+        assert!(after.is_legal(result.ponder.unwrap()));
+    }
+
+    #[test]
+    pub fn repetition_is_a_draw() {
+        // Down a rook, White goes back to a position the game has seen.
+        let mut board = Board::load_fen("6rk/6pp/8/8/8/8/r7/1R4K1 w - - 0 1".to_owned()).unwrap();
+        let mut history = vec![];
+
+        for mv in ["b1b2", "a2a1", "b2b1", "a1a2"] {
+            history.push(board.zobrist());
+            let mv = board
+                .uci_to_board_move(board.turn, UciMove::parse(mv).unwrap())
+                .unwrap();
+            board.do_move(mv).unwrap();
+        }
+
+        let mut searcher = Searcher::new(1);
+        let control = SearchControl::default();
+
+        let result = searcher.search(&board, &[], &depth(2), &control, |_| {});
+        assert!(matches!(result.score, Score::Cp(cp) if cp < -400));
+
+        let result = searcher.search(&board, &history, &depth(2), &control, |_| {});
+        assert_eq!(result.score, Score::Cp(0));
+
+        // Contempt makes the draw less attractive, still better than a rook down.
+        searcher.contempt = 50;
+        let result = searcher.search(&board, &history, &depth(2), &control, |_| {});
+        assert_eq!(result.score, Score::Cp(-50));
+    }
+
+    #[test]
+    pub fn reports_iterations() {
+        let board = Board::load_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1".to_owned()).unwrap();
+        let mut depths = vec![];
+
+        Searcher::new(1).search(&board, &[], &depth(4), &SearchControl::default(), |info| {
+            assert!(!info.pv.is_empty());
+            assert!(info.to_uci(&board).contains(" pv d2d5"));
+            depths.push(info.depth);
+        });
+
+        assert_eq!(depths, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    pub fn wdl() {
+        assert_eq!(Score::Cp(100).wdl().0, 500);
+        assert_eq!(Score::Mate(3).wdl(), (1000, 0, 0));
+        assert_eq!(Score::Mate(-1).wdl(), (0, 0, 1000));
+
+        let (win, draw, loss) = Score::Cp(0).wdl();
+        assert_eq!(win, loss);
+        assert_eq!(win + draw + loss, 1000);
+
+        let board = Board::load_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1".to_owned()).unwrap();
+        let mut searcher = Searcher::new(1);
+
+        searcher.search(&board, &[], &depth(1), &SearchControl::default(), |info| {
+            assert!(!info.to_uci(&board).contains(" wdl "));
+        });
+
+        searcher.show_wdl = true;
+        searcher.search(&board, &[], &depth(1), &SearchControl::default(), |info| {
+            let (win, draw, loss) = info.score.wdl();
+            assert!(info
+                .to_uci(&board)
+                .contains(&format!(" wdl {} {} {} nodes ", win, draw, loss)));
+        });
+    }
+
+    #[test]
+    pub fn stats() {
+        let (_, result) = search(
+            "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3",
+            depth(4),
+        );
+        let stats = result.stats;
+
+        assert!(stats.tt_hits > 0 && stats.tt_hits <= stats.tt_probes);
+        assert!(stats.tt_cutoffs <= stats.tt_hits);
+        assert!(stats.first_move_cutoffs > 0 && stats.first_move_cutoffs <= stats.beta_cutoffs);
+        assert!(stats.hashfull > 0);
+        assert!(stats.to_string().starts_with("tt probes "));
+    }
+
+    #[test]
+    pub fn multipv() {
+        let board = Board::load_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1".to_owned()).unwrap();
+
+        let mut searcher = Searcher::new(1);
+        searcher.multipv = 3;
+
+        let mut lines = vec![];
+        let result = searcher.search(&board, &[], &depth(3), &SearchControl::default(), |info| {
+            if info.depth == 3 {
+                lines.push(info.clone());
+            }
+        });
+
+        assert_eq!(
+            lines.iter().map(|info| info.multipv).collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+        assert_eq!(lines[0].pv[0], result.best.unwrap());
+        assert!(lines[1].to_uci(&board).contains(" multipv 2 "));
+
+        // Different root moves, best first.
+        let roots = lines.iter().map(|info| info.pv[0]).collect::<Vec<_>>();
+        assert!(roots[0] != roots[1] && roots[1] != roots[2] && roots[0] != roots[2]);
+
+        let scores = lines
+            .iter()
+            .map(|info| match info.score {
+                Score::Cp(cp) => cp,
+                Score::Mate(_) => panic!("no mate here"),
+            })
+            .collect::<Vec<_>>();
+        assert!(scores[0] >= scores[1] && scores[1] >= scores[2]);
+        assert!(scores[0] > scores[1] + 400);
+
+        // No more lines than there are moves.
+        searcher.multipv = 50;
+        let board = Board::load_fen("7k/8/6K1/8/8/8/8/8 b - - 0 1".to_owned()).unwrap();
+        let mut count = 0;
+
+        searcher.search(&board, &[], &depth(1), &SearchControl::default(), |_| {
+            count += 1
+        });
+        assert_eq!(count, board.generate_moves(board.turn).len());
+    }
+
+    #[test]
+    pub fn searchmoves() {
+        let board = Board::load_fen("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1".to_owned()).unwrap();
+        let parse = |mv| {
+            board
+                .uci_to_board_move(board.turn, UciMove::parse(mv).unwrap())
+                .unwrap()
+        };
+
+        let mut searcher = Searcher::new(1);
+        searcher.multipv = 3;
+
+        // Only the root is restricted: the queen still takes the rook below
+        // it, unless the rook gets out of the way.
+        let limits = Limits {
+            searchmoves: vec![parse("d2d4"), parse("d2d3"), parse("d2h2")],
+            ..depth(3)
+        };
+
+        let mut lines = vec![];
+        let result = searcher.search(&board, &[], &limits, &SearchControl::default(), |info| {
+            if info.depth == 3 {
+                lines.push(info.pv.clone());
+            }
+        });
+
+        assert_eq!(result.best, Some(parse("d2h2")));
+        assert!(matches!(result.score, Score::Cp(cp) if cp > -800));
+        assert_eq!(lines.len(), 3);
+        assert!(lines.iter().all(|pv| limits.searchmoves.contains(&pv[0])));
+
+        // MultiPV shows no more lines than there are moves to search.
+        let limits = Limits {
+            searchmoves: vec![parse("d2d5"), parse("e1e2")],
+            ..depth(2)
+        };
+
+        let mut count = 0;
+        let result = searcher.search(&board, &[], &limits, &SearchControl::default(), |info| {
+            count += (info.depth == 2) as usize;
+        });
+
+        assert_eq!(result.best, Some(parse("d2d5")));
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    pub fn stops() {
+        let board = Board::load_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1".to_owned(),
+        )
+        .unwrap();
+
+        // Stopped before it started, it still has a legal move to play.
+        let control = SearchControl::default();
+        control.stop();
+
+        let result = Searcher::new(1).search(&board, &[], &Limits::default(), &control, |_| {});
+        assert!(board.is_legal(result.best.unwrap()));
+
+        let result = Searcher::new(1).search(
+            &board,
+            &[],
+            &Limits {
+                nodes: Some(5000),
+                ..Default::default()
+            },
+            &SearchControl::default(),
+            |_| {},
+        );
+        assert!(result.nodes <= 5000);
+        assert!(board.is_legal(result.best.unwrap()));
+    }
+
+    #[test]
+    pub fn pondering_ignores_the_clock() {
+        let board =
+            Board::load_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_owned())
+                .unwrap();
+
+        let limits = Limits {
+            depth: Some(3),
+            movetime: Some(Duration::ZERO),
+            ..Default::default()
+        };
+
+        // Out of time straight away unless pondering.
+        let result =
+            Searcher::new(1).search(&board, &[], &limits, &SearchControl::default(), |_| {});
+        assert_eq!(result.depth, 0);
+
+        let control = SearchControl::new(true);
+        let result = Searcher::new(1).search(&board, &[], &limits, &control, |_| {});
+        assert_eq!(result.depth, 3);
+
+        // After ponderhit the time spent pondering counts.
+        control.ponderhit();
+        let result = Searcher::new(1).search(&board, &[], &limits, &control, |_| {});
+        assert_eq!(result.depth, 0);
+    }
+
+    #[test]
+    pub fn time_budget() {
+        let limits = Limits {
+            time: Some(Duration::from_secs(60)),
+            increment: Duration::from_secs(1),
+            move_overhead: Duration::from_millis(10),
+            ..Default::default()
+        };
+
+        let (optimum, maximum) = limits.time_budget().unwrap();
+        assert!(optimum < maximum);
+        assert!(maximum < Duration::from_secs(10));
+
+        // Never more than what's left on the clock.
+        let limits = Limits {
+            time: Some(Duration::from_millis(100)),
+            increment: Duration::from_secs(5),
+            moves_to_go: Some(1),
+            move_overhead: Duration::from_millis(10),
+            ..Default::default()
+        };
+        assert_eq!(limits.time_budget().unwrap().1, Duration::from_millis(90));
+
+        assert_eq!(Limits::default().time_budget(), None);
+    }
+}
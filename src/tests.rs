@@ -3,7 +3,12 @@
 
 #[cfg(test)]
 mod perft{
-    use crate::{board::Board, utils};
+    use crate::{
+        board::Board,
+        epd::Epd,
+        perft::{self, PerftOptions},
+        utils,
+    };
 
 
     #[test]
@@ -28,5 +33,63 @@ mod perft{
             assert_eq!(res, RESULTS[depth as usize], "Perft({}) returned an incorrect value.", depth);
         }
     }
-}
 
+    /// Well-known perft positions with their node counts, one EPD record per
+    /// line (`;D<depth> <nodes>` operations, as in `perftsuite.epd` files).
+    const SUITE: &str = include_str!("perftsuite.epd");
+
+    /// Runs every depth of the suite whose node count is at most `max_nodes`
+    /// through `perft`, checking that the board is left as it was found.
+    fn run_suite(max_nodes: u64, perft: impl Fn(&mut Board, u32) -> u64) {
+        for (line, record) in SUITE.lines().enumerate() {
+            if record.trim().is_empty() || record.starts_with('#') {
+                continue;
+            }
+
+            let epd = Epd::parse(record)
+                .unwrap_or_else(|err| panic!("perftsuite.epd:{}: {}", line + 1, err));
+
+            let name = epd.id().unwrap_or(record);
+            let mut board = epd.board.clone();
+
+            for (depth, expected) in epd.perft() {
+                if expected > max_nodes {
+                    break;
+                }
+
+                let res = perft(&mut board, depth);
+
+                assert_eq!(
+                    res, expected,
+                    "Perft({}) of {} returned an incorrect value.",
+                    depth, name
+                );
+                assert_eq!(board, epd.board, "Perft({}) of {} changed the board.", depth, name);
+            }
+        }
+    }
+
+    fn fast_perft(board: &mut Board, depth: u32) -> u64 {
+        perft::perft(board, depth, PerftOptions { threads: 4, hash_mb: 4 }).nodes
+    }
+
+    /// The shallow end of the suite, quick enough for every `cargo test`.
+    #[test]
+    pub fn suite() {
+        run_suite(100_000, |board, depth| utils::perft(board, depth, depth, &mut None));
+    }
+
+    /// A little deeper with the hashed, threaded perft.
+    #[test]
+    pub fn suite_fast() {
+        run_suite(250_000, fast_perft);
+    }
+
+    /// The whole suite. Takes a while, best run in release mode with
+    /// `cargo test --release -- --ignored`.
+    #[test]
+    #[ignore = "slow, run with --release -- --ignored"]
+    pub fn suite_deep() {
+        run_suite(u64::MAX, fast_perft);
+    }
+}
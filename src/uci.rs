@@ -8,11 +8,29 @@ pub struct UciMove {
     pub target_square: Square,
     pub promotion: Option<Piece>,
 }
+
+/// Long algebraic notation as sent to the GUI, e.g. `e7e8q`.
+impl core::fmt::Display for UciMove {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{}",
+            self.starting_square.to_string().to_ascii_lowercase(),
+            self.target_square.to_string().to_ascii_lowercase()
+        )?;
+
+        match self.promotion {
+            Some(piece) => write!(f, "{}", piece.notation().to_ascii_lowercase()),
+            None => Ok(()),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UciFen(pub(self) String);
 
 impl UciFen {
-    pub const FEN_PART_COUNT: usize= 6;
+    pub const FEN_PART_COUNT: usize = 6;
     pub const FEN_PREALLOC_SIZE: usize = 14;
 
     pub fn new(s: &str) -> Self {
@@ -20,16 +38,13 @@ impl UciFen {
     }
 
     pub fn from_cmdline<'c>(i: &mut impl core::iter::Iterator<Item = &'c str>) -> Option<Self> {
-   
-
         let mut buf = String::from_str(i.next()?).ok()?;
-        
+
         buf.reserve(Self::FEN_PREALLOC_SIZE);
 
-        for _ in 0..Self::FEN_PART_COUNT-1 {
+        for _ in 0..Self::FEN_PART_COUNT - 1 {
             buf.push(' ');
             buf.push_str(i.next()?);
-
         }
 
         Some(Self(buf))
@@ -40,23 +55,224 @@ impl UciFen {
     }
 }
 
+/// Arguments of `go`, times in milliseconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UciGo {
+    pub wtime: Option<u64>,
+    pub btime: Option<u64>,
+    pub winc: Option<u64>,
+    pub binc: Option<u64>,
+    pub movestogo: Option<u32>,
+    pub depth: Option<u32>,
+    pub nodes: Option<u64>,
+    pub mate: Option<u32>,
+    pub movetime: Option<u64>,
+    pub infinite: bool,
+    /// Search on the opponent's time until `ponderhit` or `stop`.
+    pub ponder: bool,
+    /// Root moves to choose from, all of them if empty.
+    pub searchmoves: Vec<UciMove>,
+}
+
+impl UciGo {
+    fn parse(tokens: &mut Tokens) -> Result<Self, UciParseError> {
+        // Some GUIs send a negative time once the clock has run out.
+        fn time(tokens: &mut Tokens, what: &'static str) -> Result<u64, UciParseError> {
+            Ok(tokens.number::<i64>(what)?.max(0) as u64)
+        }
+
+        let mut go = Self::default();
+
+        while let Some(token) = tokens.next() {
+            match token {
+                "wtime" => go.wtime = Some(time(tokens, "wtime value")?),
+                "btime" => go.btime = Some(time(tokens, "btime value")?),
+                "winc" => go.winc = Some(time(tokens, "winc value")?),
+                "binc" => go.binc = Some(time(tokens, "binc value")?),
+                "movestogo" => go.movestogo = Some(tokens.number("movestogo value")?),
+                "depth" => go.depth = Some(tokens.number("depth value")?),
+                "nodes" => go.nodes = Some(tokens.number("nodes value")?),
+                "mate" => go.mate = Some(tokens.number("mate value")?),
+                "movetime" => go.movetime = Some(time(tokens, "movetime value")?),
+                "infinite" => go.infinite = true,
+                "ponder" => go.ponder = true,
+                // The moves run up to the next argument.
+                "searchmoves" => {
+                    while let Some(mv) = tokens.peek().and_then(UciMove::parse) {
+                        go.searchmoves.push(mv);
+                        tokens.next();
+                    }
+
+                    if go.searchmoves.is_empty() {
+                        return Err(match tokens.next() {
+                            Some(token) => {
+                                tokens.error(UciParseErrorKind::InvalidMove(token.to_owned()))
+                            }
+                            None => tokens.missing("search moves"),
+                        });
+                    }
+                }
+                token => return Err(tokens.unexpected(token)),
+            }
+        }
+
+        Ok(go)
+    }
+}
+
+/// What is wrong with a command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UciParseErrorKind {
+    /// Nothing but whitespace.
+    Empty,
+    UnknownCommand(String),
+    /// The line ended where this was expected.
+    Missing(&'static str),
+    /// A token that doesn't belong where it is.
+    Unexpected(String),
+    InvalidNumber(String),
+    InvalidMove(String),
+}
+
+/// A command line that couldn't be parsed, and where.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UciParseError {
+    /// Index of the offending token, the command being token 0. For
+    /// [UciParseErrorKind::Missing], the number of tokens on the line.
+    pub position: usize,
+    pub kind: UciParseErrorKind,
+}
+
+impl core::fmt::Display for UciParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.kind {
+            UciParseErrorKind::Empty => write!(f, "empty command")?,
+            UciParseErrorKind::UnknownCommand(cmd) => write!(f, "unknown command '{}'", cmd)?,
+            UciParseErrorKind::Missing(what) => write!(f, "expected {}", what)?,
+            UciParseErrorKind::Unexpected(token) => write!(f, "unexpected '{}'", token)?,
+            UciParseErrorKind::InvalidNumber(token) => write!(f, "invalid number '{}'", token)?,
+            UciParseErrorKind::InvalidMove(token) => write!(f, "invalid move '{}'", token)?,
+        }
+
+        write!(f, " at token {}", self.position)
+    }
+}
+
+impl std::error::Error for UciParseError {}
+
+/// The whitespace separated tokens of a command line, keeping count of
+/// where they are for errors.
+pub struct Tokens<'a> {
+    parts: core::iter::Peekable<core::str::SplitAsciiWhitespace<'a>>,
+    /// Index of the next token.
+    position: usize,
+}
+
+impl<'a> Tokens<'a> {
+    pub fn new(line: &'a str) -> Self {
+        Self {
+            parts: line.split_ascii_whitespace().peekable(),
+            position: 0,
+        }
+    }
+
+    pub fn peek(&mut self) -> Option<&'a str> {
+        self.parts.peek().copied()
+    }
+
+    /// An error about the token just read.
+    fn error(&self, kind: UciParseErrorKind) -> UciParseError {
+        UciParseError {
+            position: self.position.saturating_sub(1),
+            kind,
+        }
+    }
+
+    fn unexpected(&self, token: &str) -> UciParseError {
+        self.error(UciParseErrorKind::Unexpected(token.to_owned()))
+    }
+
+    /// An error about the line ending early.
+    fn missing(&self, what: &'static str) -> UciParseError {
+        UciParseError {
+            position: self.position,
+            kind: UciParseErrorKind::Missing(what),
+        }
+    }
+
+    fn expect(&mut self, what: &'static str) -> Result<&'a str, UciParseError> {
+        self.next().ok_or_else(|| self.missing(what))
+    }
+
+    fn number<T: FromStr>(&mut self, what: &'static str) -> Result<T, UciParseError> {
+        let token = self.expect(what)?;
+
+        token
+            .parse()
+            .map_err(|_| self.error(UciParseErrorKind::InvalidNumber(token.to_owned())))
+    }
+
+    /// Fails on anything left on the line.
+    fn end(&mut self) -> Result<(), UciParseError> {
+        match self.next() {
+            Some(token) => Err(self.unexpected(token)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = &'a str;
 
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.parts.next()?;
+        self.position += 1;
+
+        Some(token)
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq, ::strum_macros::EnumString, ::strum_macros::Display)]
 
 pub enum UciCommand {
+    Uci,
+    IsReady,
+    UciNewGame,
     Debug(bool),
     Perft(u32),
     Position {
         fen: Option<UciFen>,
         moves: Vec<UciMove>,
     },
+    SetOption {
+        name: String,
+        value: Option<String>,
+    },
+    Go(UciGo),
+    PonderHit,
     Stop,
     Quit,
+    /// Non-standard, for debugging by hand: draws the board.
+    D,
+    /// Non-standard: the static evaluation, term by term.
+    Eval,
+    /// Non-standard: swaps the colors of the position.
+    Flip,
+    /// Non-standard: lists the legal moves.
+    Moves,
+    /// Non-standard: searches the bench positions, to the given depth or the
+    /// default one.
+    Bench(Option<u32>),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ::strum_macros::EnumString)]
 pub enum UciRawCommand {
+    #[strum(ascii_case_insensitive)]
+    Uci,
+    #[strum(ascii_case_insensitive)]
+    IsReady,
+    #[strum(ascii_case_insensitive)]
+    UciNewGame,
     #[strum(ascii_case_insensitive)]
     Debug,
     #[strum(ascii_case_insensitive)]
@@ -64,63 +280,160 @@ pub enum UciRawCommand {
     #[strum(ascii_case_insensitive)]
     Position,
     #[strum(ascii_case_insensitive)]
+    SetOption,
+    #[strum(ascii_case_insensitive)]
     Stop,
     #[strum(ascii_case_insensitive)]
     Go,
     #[strum(ascii_case_insensitive)]
+    PonderHit,
+    #[strum(ascii_case_insensitive)]
     Quit,
+    #[strum(ascii_case_insensitive)]
+    D,
+    #[strum(ascii_case_insensitive)]
+    Eval,
+    #[strum(ascii_case_insensitive)]
+    Flip,
+    #[strum(ascii_case_insensitive)]
+    Moves,
+    #[strum(ascii_case_insensitive)]
+    Bench,
 }
 
 impl UciRawCommand {
+    /// The command named by the first token.
     pub fn parse<T: AsRef<str>>(cmdline: &mut impl Iterator<Item = T>) -> Option<Self> {
-        for cmd in cmdline {
-            if let Ok(parsed) = Self::from_str(cmd.as_ref()) {
-                return Some(parsed);
-            }
-        }
-
-        None
+        Self::from_str(cmdline.next()?.as_ref()).ok()
     }
 }
 
 impl UciCommand {
-    pub fn try_parse(s: String) -> Option<UciCommand> {
-        let mut parts = s.split_ascii_whitespace();
-
-        let raw = UciRawCommand::parse(&mut parts)?;
-
-        match raw {
-            UciRawCommand::Debug => todo!(),
-            UciRawCommand::Perft => Some(UciCommand::Perft(parts.next()?.parse::<u32>().ok()?)),
+    pub fn try_parse(s: String) -> Result<UciCommand, UciParseError> {
+        let mut tokens = Tokens::new(&s);
+
+        let name = tokens.next().ok_or(UciParseError {
+            position: 0,
+            kind: UciParseErrorKind::Empty,
+        })?;
+
+        let raw = UciRawCommand::from_str(name)
+            .map_err(|_| tokens.error(UciParseErrorKind::UnknownCommand(name.to_owned())))?;
+
+        let cmd = match raw {
+            UciRawCommand::Uci => UciCommand::Uci,
+            UciRawCommand::IsReady => UciCommand::IsReady,
+            UciRawCommand::UciNewGame => UciCommand::UciNewGame,
+            UciRawCommand::Debug => match tokens.expect("on or off")? {
+                "on" => UciCommand::Debug(true),
+                "off" => UciCommand::Debug(false),
+                token => return Err(tokens.unexpected(token)),
+            },
+            UciRawCommand::Perft => UciCommand::Perft(tokens.number("perft depth")?),
             UciRawCommand::Position => {
-                let fen = match parts.next()? {
-                    "fen" => Some(UciFen::from_cmdline(&mut parts)?),
+                let fen = match tokens.expect("startpos or fen")? {
                     "startpos" => None,
-                    _ => None?,
+                    "fen" => {
+                        // The clocks are optional.
+                        let mut fen = vec![];
+
+                        while let Some(part) = tokens.peek().filter(|&part| part != "moves") {
+                            tokens.next();
+
+                            if fen.len() == UciFen::FEN_PART_COUNT {
+                                return Err(tokens.unexpected(part));
+                            }
+
+                            fen.push(part);
+                        }
+
+                        if fen.len() < UciFen::FEN_PART_COUNT - 2 {
+                            return Err(tokens.missing("fen field"));
+                        }
+
+                        Some(UciFen::new(&fen.join(" ")))
+                    }
+                    token => return Err(tokens.unexpected(token)),
                 };
 
-                let moves = Self::position_get_moves_helper(&mut parts);
+                let moves = Self::position_get_moves_helper(&mut tokens)?;
 
-                Some(UciCommand::Position { fen, moves })
+                UciCommand::Position { fen, moves }
             }
-            UciRawCommand::Stop => Some(UciCommand::Stop),
-            UciRawCommand::Go => todo!(),
-            UciRawCommand::Quit => Some(UciCommand::Quit),
-        }
+            UciRawCommand::SetOption => {
+                match tokens.expect("name")? {
+                    "name" => {}
+                    token => return Err(tokens.unexpected(token)),
+                }
+
+                // Option names and values may contain spaces.
+                let mut name = vec![];
+                let mut value = None::<Vec<&str>>;
+
+                while let Some(part) = tokens.next() {
+                    match &mut value {
+                        None if part == "value" && name.is_empty() => {
+                            return Err(tokens.unexpected(part));
+                        }
+                        None if part == "value" => value = Some(vec![]),
+                        None => name.push(part),
+                        Some(value) => value.push(part),
+                    }
+                }
+
+                if name.is_empty() {
+                    return Err(tokens.missing("option name"));
+                }
+
+                UciCommand::SetOption {
+                    name: name.join(" "),
+                    value: value.map(|v| v.join(" ")),
+                }
+            }
+            UciRawCommand::Stop => UciCommand::Stop,
+            UciRawCommand::Go => match tokens.peek() {
+                Some("perft") => {
+                    tokens.next();
+                    UciCommand::Perft(tokens.number("perft depth")?)
+                }
+                _ => UciCommand::Go(UciGo::parse(&mut tokens)?),
+            },
+            UciRawCommand::PonderHit => UciCommand::PonderHit,
+            UciRawCommand::Quit => UciCommand::Quit,
+            UciRawCommand::D => UciCommand::D,
+            UciRawCommand::Eval => UciCommand::Eval,
+            UciRawCommand::Flip => UciCommand::Flip,
+            UciRawCommand::Moves => UciCommand::Moves,
+            UciRawCommand::Bench => match tokens.peek() {
+                Some(_) => UciCommand::Bench(Some(tokens.number("bench depth")?)),
+                None => UciCommand::Bench(None),
+            },
+        };
+
+        tokens.end()?;
+
+        Ok(cmd)
     }
 
-    pub fn position_get_moves_helper<'a>(i: &mut impl Iterator<Item = &'a str>) -> Vec<UciMove> {
-        if i.next() != Some("moves") {
-            return vec![];
+    /// The moves after `moves`, if that comes next.
+    pub fn position_get_moves_helper(tokens: &mut Tokens) -> Result<Vec<UciMove>, UciParseError> {
+        match tokens.next() {
+            Some("moves") => {}
+            Some(token) => return Err(tokens.unexpected(token)),
+            None => return Ok(vec![]),
         }
 
-        i.filter_map(|mv| UciMove::parse(mv)).collect()
+        tokens
+            .map(|mv| UciMove::parse(mv).ok_or(mv))
+            .collect::<Result<_, _>>()
+            .map_err(|mv| tokens.error(UciParseErrorKind::InvalidMove(mv.to_owned())))
     }
 }
 
 impl UciMove {
     pub fn parse(mv: &str) -> Option<Self> {
-        if mv.len() < 4 {
+        // Also keeps the slicing below on character boundaries.
+        if !mv.is_ascii() || !(4..=5).contains(&mv.len()) {
             None?
         }
 
@@ -129,10 +442,10 @@ impl UciMove {
 
         let promotion = match mv.len() {
             ..=4 => None,
-            _ => Piece::from_notation(mv.chars().nth(4)?),
+            _ => Piece::from_notation(mv.chars().nth(4)?.to_ascii_uppercase()),
         };
 
-        if promotion.map(|p| !p.is_sliding()).unwrap_or(false) {
+        if promotion.is_some_and(|p| !Piece::PROMOTIONS.contains(&p)) {
             None? // Cannot promote to that piece.
         }
 
@@ -170,6 +483,16 @@ pub(super) mod move_parser_tests {
                 target_square: Square::A4,
             })
         );
+
+        // GUIs send the piece in lowercase, and may underpromote.
+        assert_eq!(
+            UciMove::parse("b7a8q").and_then(|mv| mv.promotion),
+            Some(crate::Piece::Queen)
+        );
+        assert_eq!(
+            UciMove::parse("a7a8n").and_then(|mv| mv.promotion),
+            Some(crate::Piece::Knight)
+        );
     }
 
     #[test]
@@ -179,6 +502,12 @@ pub(super) mod move_parser_tests {
             None
         );
     }
+
+    #[test]
+    pub fn display() {
+        assert_eq!(UciMove::parse("e7e8q").unwrap().to_string(), "e7e8q");
+        assert_eq!(UciMove::parse("E1H1").unwrap().to_string(), "e1h1");
+    }
 }
 
 #[cfg(test)]
@@ -186,11 +515,17 @@ mod command_tests {
     use super::*;
     use crate::uci::{UciCommand, UciMove};
 
+    fn error(line: &str) -> (usize, UciParseErrorKind) {
+        let e = UciCommand::try_parse(line.to_owned()).unwrap_err();
+
+        (e.position, e.kind)
+    }
+
     #[test]
     pub fn basic_startpos() {
         assert_eq!(
             UciCommand::try_parse("position startpos".to_owned()),
-            Some(UciCommand::Position {
+            Ok(UciCommand::Position {
                 fen: None,
                 moves: vec![],
             })
@@ -201,7 +536,7 @@ mod command_tests {
     pub fn startpos_kingspawn() {
         assert_eq!(
             UciCommand::try_parse("position startpos moves e2e4 e7e5".to_owned()),
-            Some(UciCommand::Position {
+            Ok(UciCommand::Position {
                 fen: None,
                 moves: vec![
                     UciMove {
@@ -225,23 +560,363 @@ mod command_tests {
             UciCommand::try_parse(
                 "position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_owned()
             ),
-            Some(UciCommand::Position {
-                fen: Some(UciFen::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")),
+            Ok(UciCommand::Position {
+                fen: Some(UciFen::new(
+                    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+                )),
                 moves: vec![],
             })
         )
     }
 
+    #[test]
+    pub fn setoption() {
+        assert_eq!(
+            UciCommand::try_parse("setoption name UCI_Chess960 value true".to_owned()),
+            Ok(UciCommand::SetOption {
+                name: "UCI_Chess960".to_owned(),
+                value: Some("true".to_owned()),
+            })
+        );
+        assert_eq!(
+            UciCommand::try_parse("setoption name Clear Hash".to_owned()),
+            Ok(UciCommand::SetOption {
+                name: "Clear Hash".to_owned(),
+                value: None,
+            })
+        );
+        assert_eq!(
+            error("setoption value 1"),
+            (1, UciParseErrorKind::Unexpected("value".to_owned()))
+        );
+        assert_eq!(
+            error("setoption name"),
+            (2, UciParseErrorKind::Missing("option name"))
+        );
+    }
 
     #[test]
-    pub fn position_invalid_fen() {
+    pub fn handshake() {
+        assert_eq!(UciCommand::try_parse("uci".to_owned()), Ok(UciCommand::Uci));
+        assert_eq!(
+            UciCommand::try_parse("isready\n".to_owned()),
+            Ok(UciCommand::IsReady)
+        );
+        assert_eq!(
+            UciCommand::try_parse("ucinewgame".to_owned()),
+            Ok(UciCommand::UciNewGame)
+        );
+    }
+
+    #[test]
+    pub fn perft() {
+        assert_eq!(
+            UciCommand::try_parse("perft 5".to_owned()),
+            Ok(UciCommand::Perft(5))
+        );
+        assert_eq!(
+            UciCommand::try_parse("go perft 5".to_owned()),
+            Ok(UciCommand::Perft(5))
+        );
+        assert_eq!(
+            error("go perft"),
+            (2, UciParseErrorKind::Missing("perft depth"))
+        );
+    }
+
+    #[test]
+    pub fn go() {
+        assert_eq!(
+            UciCommand::try_parse("go".to_owned()),
+            Ok(UciCommand::Go(UciGo::default()))
+        );
         assert_eq!(
             UciCommand::try_parse(
-                "position fen 0000/A0A0A.? @NOT_A_FEN_STRING".to_owned()
+                "go wtime 300000 btime -20 winc 2000 binc 2000 movestogo 12".to_owned()
             ),
-            None
-        )
+            Ok(UciCommand::Go(UciGo {
+                wtime: Some(300000),
+                btime: Some(0),
+                winc: Some(2000),
+                binc: Some(2000),
+                movestogo: Some(12),
+                ..Default::default()
+            }))
+        );
+        assert_eq!(
+            UciCommand::try_parse("go depth 8 nodes 100000 mate 3 movetime 500".to_owned()),
+            Ok(UciCommand::Go(UciGo {
+                depth: Some(8),
+                nodes: Some(100000),
+                mate: Some(3),
+                movetime: Some(500),
+                ..Default::default()
+            }))
+        );
+        assert_eq!(
+            error("go depth"),
+            (2, UciParseErrorKind::Missing("depth value"))
+        );
+        assert_eq!(
+            error("go nodes many"),
+            (2, UciParseErrorKind::InvalidNumber("many".to_owned()))
+        );
+        assert_eq!(
+            error("go sideways"),
+            (1, UciParseErrorKind::Unexpected("sideways".to_owned()))
+        );
     }
 
+    #[test]
+    pub fn searchmoves() {
+        assert_eq!(
+            UciCommand::try_parse("go searchmoves e2e4 d2d4 depth 6".to_owned()),
+            Ok(UciCommand::Go(UciGo {
+                depth: Some(6),
+                searchmoves: vec![
+                    UciMove::parse("e2e4").unwrap(),
+                    UciMove::parse("d2d4").unwrap()
+                ],
+                ..Default::default()
+            }))
+        );
+        assert_eq!(
+            UciCommand::try_parse("go infinite searchmoves a7a8q".to_owned()),
+            Ok(UciCommand::Go(UciGo {
+                infinite: true,
+                searchmoves: vec![UciMove::parse("a7a8q").unwrap()],
+                ..Default::default()
+            }))
+        );
+        assert_eq!(
+            error("go searchmoves"),
+            (2, UciParseErrorKind::Missing("search moves"))
+        );
+        assert_eq!(
+            error("go searchmoves e2e9"),
+            (2, UciParseErrorKind::InvalidMove("e2e9".to_owned()))
+        );
+    }
+
+    #[test]
+    pub fn ponder() {
+        assert_eq!(
+            UciCommand::try_parse("go ponder wtime 1000 btime 1000".to_owned()),
+            Ok(UciCommand::Go(UciGo {
+                wtime: Some(1000),
+                btime: Some(1000),
+                ponder: true,
+                ..Default::default()
+            }))
+        );
+        assert_eq!(
+            UciCommand::try_parse("go infinite".to_owned()),
+            Ok(UciCommand::Go(UciGo {
+                infinite: true,
+                ..Default::default()
+            }))
+        );
+        assert_eq!(
+            UciCommand::try_parse("ponderhit".to_owned()),
+            Ok(UciCommand::PonderHit)
+        );
+        assert_eq!(
+            UciCommand::try_parse("stop".to_owned()),
+            Ok(UciCommand::Stop)
+        );
+    }
 
+    #[test]
+    pub fn position_invalid_fen() {
+        assert_eq!(
+            error("position fen 0000/A0A0A.? @NOT_A_FEN_STRING"),
+            (4, UciParseErrorKind::Missing("fen field"))
+        );
+        assert_eq!(
+            error("position fen 8/8/8/8/8/8/8/8 w - - 0 1 2"),
+            (8, UciParseErrorKind::Unexpected("2".to_owned()))
+        );
+
+        // Without the clocks.
+        assert_eq!(
+            UciCommand::try_parse("position fen 4k3/8/8/8/8/8/8/4K3 w - - moves e1e2".to_owned()),
+            Ok(UciCommand::Position {
+                fen: Some(UciFen::new("4k3/8/8/8/8/8/8/4K3 w - -")),
+                moves: vec![UciMove::parse("e1e2").unwrap()],
+            })
+        );
+    }
+
+    #[test]
+    pub fn errors() {
+        assert_eq!(error(""), (0, UciParseErrorKind::Empty));
+        assert_eq!(error(" \t "), (0, UciParseErrorKind::Empty));
+
+        // Unknown tokens aren't skipped.
+        assert_eq!(
+            error("foo position startpos"),
+            (0, UciParseErrorKind::UnknownCommand("foo".to_owned()))
+        );
+        assert_eq!(
+            error("isready now"),
+            (1, UciParseErrorKind::Unexpected("now".to_owned()))
+        );
+        assert_eq!(
+            error("position"),
+            (1, UciParseErrorKind::Missing("startpos or fen"))
+        );
+        assert_eq!(
+            error("position startpos e2e4"),
+            (2, UciParseErrorKind::Unexpected("e2e4".to_owned()))
+        );
+
+        // Moves are reported, not dropped.
+        assert_eq!(
+            error("position startpos moves e2e4 e7e9 g1f3"),
+            (4, UciParseErrorKind::InvalidMove("e7e9".to_owned()))
+        );
+        assert_eq!(
+            error("position startpos moves e2e4 e7e5é"),
+            (4, UciParseErrorKind::InvalidMove("e7e5é".to_owned()))
+        );
+
+        assert_eq!(
+            UciCommand::try_parse("perft x".to_owned())
+                .unwrap_err()
+                .to_string(),
+            "invalid number 'x' at token 1"
+        );
+    }
+
+    #[test]
+    pub fn debugging_commands() {
+        for (line, cmd) in [
+            ("d", UciCommand::D),
+            ("eval", UciCommand::Eval),
+            ("flip", UciCommand::Flip),
+            ("moves", UciCommand::Moves),
+            ("bench", UciCommand::Bench(None)),
+            ("bench 4", UciCommand::Bench(Some(4))),
+        ] {
+            assert_eq!(UciCommand::try_parse(line.to_owned()), Ok(cmd));
+        }
+
+        assert_eq!(
+            error("d 1"),
+            (1, UciParseErrorKind::Unexpected("1".to_owned()))
+        );
+        assert_eq!(
+            error("bench deep"),
+            (1, UciParseErrorKind::InvalidNumber("deep".to_owned()))
+        );
+    }
+
+    #[test]
+    pub fn debug() {
+        assert_eq!(
+            UciCommand::try_parse("debug on".to_owned()),
+            Ok(UciCommand::Debug(true))
+        );
+        assert_eq!(
+            UciCommand::try_parse("debug off".to_owned()),
+            Ok(UciCommand::Debug(false))
+        );
+        assert_eq!(error("debug"), (1, UciParseErrorKind::Missing("on or off")));
+        assert_eq!(
+            error("debug maybe"),
+            (1, UciParseErrorKind::Unexpected("maybe".to_owned()))
+        );
+    }
+
+    /// Random command lines, made of protocol words, numbers, moves and junk,
+    /// never panic and report an error position on the line.
+    #[test]
+    pub fn random_lines() {
+        const WORDS: [&str; 36] = [
+            "uci",
+            "bench",
+            "d",
+            "eval",
+            "flip",
+            "isready",
+            "ucinewgame",
+            "debug",
+            "on",
+            "off",
+            "perft",
+            "position",
+            "startpos",
+            "fen",
+            "moves",
+            "setoption",
+            "name",
+            "value",
+            "go",
+            "stop",
+            "ponderhit",
+            "quit",
+            "wtime",
+            "btime",
+            "winc",
+            "binc",
+            "movestogo",
+            "depth",
+            "nodes",
+            "mate",
+            "movetime",
+            "infinite",
+            "ponder",
+            "searchmoves",
+            "8/8/8/8/8/8/8/8",
+            "w",
+        ];
+
+        // xorshift64, so failures reproduce.
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut random = move |n: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize % n
+        };
+
+        for _ in 0..20_000 {
+            let count = random(9);
+            let mut tokens = vec![];
+
+            for _ in 0..count {
+                let token = match random(5) {
+                    0 | 1 => WORDS[random(WORDS.len())].to_owned(),
+                    2 => (random(2_000_000) as i64 - 1000).to_string(),
+                    3 => {
+                        let square = |r: &mut dyn FnMut(usize) -> usize| {
+                            format!("{}{}", (b'a' + r(9) as u8) as char, r(10))
+                        };
+                        format!(
+                            "{}{}{}",
+                            square(&mut random),
+                            square(&mut random),
+                            ["", "q", "n", "k", "x"][random(5)]
+                        )
+                    }
+                    _ => (0..1 + random(6))
+                        .map(|_| ['a', 'Z', '9', '-', '/', 'é', '♞', '\u{0}'][random(8)])
+                        .collect(),
+                };
+
+                tokens.push(token);
+            }
+
+            let line = tokens.join(["  ", " ", "\t"][random(3)]);
+
+            match UciCommand::try_parse(line.clone()) {
+                Ok(_) => assert!(
+                    UciRawCommand::parse(&mut tokens.iter()).is_some(),
+                    "{:?}",
+                    line
+                ),
+                Err(e) => assert!(e.position <= tokens.len(), "{:?}: {}", line, e),
+            }
+        }
+    }
 }
@@ -1,12 +1,13 @@
+/// Draws `board` on stderr, `%` for set squares, out of the way of the UCI
+/// output on stdout.
 #[allow(unused)]
-
 pub fn print_bitboard(board: BitBoard) {
     for rank in (0..8).rev() {
         // Iterate from 7 to 0 for standard chessboard representation
         for file in 0..8 {
             // Correctly calculate the bit position
             let bit_position = (rank * 8 + file) as u64;
-            print!(
+            eprint!(
                 "{} ",
                 if board.0 & (1 << bit_position) != 0 {
                     '%'
@@ -15,7 +16,7 @@ pub fn print_bitboard(board: BitBoard) {
                 }
             );
         }
-        println!(); // Print a newline after each rank
+        eprintln!(); // Print a newline after each rank
     }
 }
 
@@ -52,8 +53,6 @@ pub fn perft(
         }
     */
 
-    board.prepare();
-
     for r#move in board.generate_moves(board.turn) {
         let res;
 
@@ -61,7 +60,6 @@ pub fn perft(
 
         // println!("{} {}", path, r#move);
 
-        //:w;:wprint_bitboard(board.bitboards.0[BitBoards::ad_bitboard(board.turn.opponent())]);
         board.do_move(r#move).unwrap();
 
         res = perft(board, start_depth, depth - 1, root_nodes);
@@ -74,8 +72,6 @@ pub fn perft(
                 *root_nodes = Some(vec![]);
             }
             root_nodes.as_mut().unwrap().push((r#move, res));
-
-            // print_bitboard(board.bitboards.0[BitBoards::ad_bitboard(board.turn)])
         }
         //nodes += res;
     }
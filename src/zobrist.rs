@@ -0,0 +1,195 @@
+//! Zobrist hashing: a 64-bit key per position, built by XOR-ing a fixed random
+//! number for every feature of the position.
+
+use crate::{
+    board::Board,
+    piece::{Color, Piece},
+    square::*,
+};
+
+/// Keys for every piece on every square, indexed by color, [Piece] value
+/// (offset by [Piece::King]) and square.
+const PIECE_KEYS: [[[u64; Square::NUM]; 6]; 2] = build_piece_keys();
+
+/// XOR-ed in when Black is to move.
+const BLACK_TO_MOVE: u64 = splitmix64(0x5a0b_c1d2_e3f4_0617).0;
+
+/// Keys for the castling rooks by color and [crate::r#move::CastlingMethod],
+/// combined with the rook's file so Chess960 rights stay distinct.
+const CASTLING_KEYS: [[[u64; 8]; 2]; 2] = build_castling_keys();
+
+/// Keys for the file of a usable en passant square.
+const EN_PASSANT_KEYS: [u64; 8] = build_keys(0x0e9a_55a7_d5f1_3c2b);
+
+/// SplitMix64, returning the output and the next state.
+const fn splitmix64(state: u64) -> (u64, u64) {
+    let state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+
+    (z ^ (z >> 31), state)
+}
+
+const fn build_keys<const N: usize>(seed: u64) -> [u64; N] {
+    let mut keys = [0; N];
+    let mut state = seed;
+
+    let mut i = 0;
+    while i < N {
+        let (key, next) = splitmix64(state);
+        keys[i] = key;
+        state = next;
+        i += 1;
+    }
+
+    keys
+}
+
+const fn build_piece_keys() -> [[[u64; Square::NUM]; 6]; 2] {
+    let mut keys = [[[0; Square::NUM]; 6]; 2];
+    let mut state = 0x2545_f491_4f6c_dd1d;
+
+    let mut color = 0;
+    while color < 2 {
+        let mut piece = 0;
+        while piece < 6 {
+            let mut square = 0;
+            while square < Square::NUM {
+                let (key, next) = splitmix64(state);
+                keys[color][piece][square] = key;
+                state = next;
+                square += 1;
+            }
+            piece += 1;
+        }
+        color += 1;
+    }
+
+    keys
+}
+
+const fn build_castling_keys() -> [[[u64; 8]; 2]; 2] {
+    let mut keys = [[[0; 8]; 2]; 2];
+    let mut state = 0x7c3a_9d1e_42b8_f065;
+
+    let mut color = 0;
+    while color < 2 {
+        let mut method = 0;
+        while method < 2 {
+            let mut file = 0;
+            while file < 8 {
+                let (key, next) = splitmix64(state);
+                keys[color][method][file] = key;
+                state = next;
+                file += 1;
+            }
+            method += 1;
+        }
+        color += 1;
+    }
+
+    keys
+}
+
+fn piece_key(color: Color, piece: Piece, square: Square) -> u64 {
+    PIECE_KEYS[color as usize][piece as usize - Piece::King as usize][square as usize]
+}
+
+impl Board {
+    /// Zobrist key of the position: pieces, side to move, castling rights and
+    /// the en passant square if it can be used. Clocks are left out, so
+    /// positions that compare equal apart from them share a key.
+    pub fn zobrist(&self) -> u64 {
+        let mut key = 0;
+
+        for square in Square::ALL {
+            if let Some((color, piece)) = self.squares[square as usize] {
+                key ^= piece_key(color, piece, square);
+            }
+        }
+
+        if self.turn == Color::Black {
+            key ^= BLACK_TO_MOVE;
+        }
+
+        for (color, rooks) in self.castling_rights().into_iter().enumerate() {
+            for (method, rook) in rooks.into_iter().enumerate() {
+                if let Some(rook) = rook {
+                    key ^= CASTLING_KEYS[color][method][rook.file() as usize];
+                }
+            }
+        }
+
+        if let Some(square) = self.en_passant_square() {
+            key ^= EN_PASSANT_KEYS[square.file() as usize];
+        }
+
+        key
+    }
+}
+
+#[cfg(test)]
+mod zobrist_tests {
+    use super::*;
+    use crate::uci::UciMove;
+
+    fn board(fen: &str) -> Board {
+        Board::load_fen(fen.to_owned()).unwrap()
+    }
+
+    fn play(board: &mut Board, moves: &str) {
+        for mv in moves.split_whitespace() {
+            let mv = board
+                .uci_to_board_move(board.turn, UciMove::parse(mv).unwrap())
+                .unwrap();
+            board.do_move(mv).unwrap();
+        }
+    }
+
+    #[test]
+    pub fn transpositions() {
+        let mut a = board("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        let mut b = a.clone();
+        let start = a.zobrist();
+
+        play(&mut a, "g1f3 g8f6 b1c3");
+        play(&mut b, "b1c3 g8f6 g1f3");
+
+        assert_eq!(a.zobrist(), b.zobrist());
+        assert_ne!(a.zobrist(), start);
+        assert_eq!(
+            a.zobrist(),
+            board("rnbqkb1r/pppppppp/5n2/8/8/2N2N2/PPPPPPPP/R1BQKB1R b KQkq - 3 2").zobrist()
+        );
+    }
+
+    #[test]
+    pub fn state_is_hashed() {
+        let keys = [
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K2R w Kkq - 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K2R w Qkq - 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K2R w - - 0 1",
+        ]
+        .map(|fen| board(fen).zobrist());
+
+        for (i, a) in keys.iter().enumerate() {
+            for b in &keys[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+
+        // Only an en passant square a pawn can capture on changes the key.
+        assert_ne!(
+            board("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").zobrist(),
+            board("4k3/8/8/3pP3/8/8/8/4K3 w - - 0 1").zobrist()
+        );
+        assert_eq!(
+            board("4k3/8/8/3p4/8/8/4P3/4K3 w - d6 0 1").zobrist(),
+            board("4k3/8/8/3p4/8/8/4P3/4K3 w - - 0 1").zobrist()
+        );
+    }
+}